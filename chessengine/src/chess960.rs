@@ -0,0 +1,212 @@
+/*
+    ////////////////
+    /// Chess960 ///
+    ////////////////
+
+    Chess960 (Fischer Random) shuffles the back rank: the bishops stand on squares of opposite colors and the king stands
+    somewhere between the two rooks. Black mirrors white. That leaves exactly 960 start positions, which are numbered the
+    usual way (Scharnagl's numbering, see https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme):
+
+    1. index % 4 puts the light squared bishop on the b, d, f or h file, then index / 4 is carried on;
+    2. that % 4 puts the dark squared bishop on the a, c, e or g file;
+    3. that % 6 puts the queen on one of the six squares still empty;
+    4. the rest (0..10) picks two of the five remaining squares for the knights, see `KNIGHT_SQUARES`;
+    5. rook, king and rook fill the last three squares from left to right.
+
+    Number 518 is the normal starting position. Castling works as described in movegen.rs.
+*/
+
+use crate::{CastlingRights, Color, Game, Piece, PieceType};
+
+pub const POSITION_COUNT: usize = 960;
+
+// Which two of the five squares left after placing bishops and queen the knights take, for each value 0..10.
+const KNIGHT_SQUARES: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+// The back rank of start position `index`, from the a-file to the h-file.
+fn back_rank(index: usize) -> [PieceType; 8] {
+    let mut rank = [None; 8];
+    let mut rest = index;
+
+    rank[(rest % 4) * 2 + 1] = Some(PieceType::Bishop);
+    rest /= 4;
+    rank[(rest % 4) * 2] = Some(PieceType::Bishop);
+    rest /= 4;
+
+    // Puts a piece on the `nth` square that is still empty
+    let place = |rank: &mut [Option<PieceType>; 8], nth: usize, piece_type: PieceType| {
+        let file = (0..8).filter(|&file| rank[file].is_none()).nth(nth).expect("there are enough empty squares left");
+        rank[file] = Some(piece_type);
+    };
+
+    place(&mut rank, rest % 6, PieceType::Queen);
+    rest /= 6;
+
+    // The second knight goes first, so placing it doesn't shift the square the first one is counted on
+    let (first, second) = KNIGHT_SQUARES[rest];
+    place(&mut rank, second, PieceType::Knight);
+    place(&mut rank, first, PieceType::Knight);
+
+    for piece_type in [PieceType::Rook, PieceType::King, PieceType::Rook] {
+        place(&mut rank, 0, piece_type);
+    }
+
+    rank.map(|piece_type| piece_type.expect("every square has been filled"))
+}
+
+impl Game {
+
+    // Chess960 start position number `index`, or None if there is no such position (`index` is 960 or more).
+    pub fn chess960(index: usize) -> Option<Game> {
+        if index >= POSITION_COUNT {
+            return None;
+        }
+
+        let mut game = Game::empty();
+        game.chess960 = true;
+        game.castling_rights = CastlingRights::ALL;
+
+        let mut rook_files = vec![];
+        for (file, piece_type) in back_rank(index).into_iter().enumerate() {
+            game.put_piece(file, Piece { color: Color::White, piece_type });
+            game.put_piece(8 + file, Piece { color: Color::White, piece_type: PieceType::Pawn });
+            game.put_piece(48 + file, Piece { color: Color::Black, piece_type: PieceType::Pawn });
+            game.put_piece(56 + file, Piece { color: Color::Black, piece_type });
+            if piece_type == PieceType::Rook {
+                rook_files.push(file);
+            }
+        }
+
+        // The rook further right castles kingside
+        game.castling_rooks = [[rook_files[1], rook_files[0]], [56 + rook_files[1], 56 + rook_files[0]]];
+
+        game.hash = game.compute_hash();
+        Some(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::MoveKind;
+    use crate::{perft, STARTING_FEN};
+
+    #[test]
+    fn numbering() {
+        assert_eq!(Game::chess960(518).unwrap().to_FEN(), STARTING_FEN);
+        assert!(Game::chess960(518).unwrap() == Game::initialize());
+        assert_eq!(Game::chess960(0).unwrap().to_FEN(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(Game::chess960(959).unwrap().to_FEN(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+        assert!(Game::chess960(960).is_none());
+    }
+
+    #[test]
+    fn every_start_position_is_different_and_valid() {
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..POSITION_COUNT {
+            let rank = back_rank(index);
+            assert!(seen.insert(rank.map(|piece_type| piece_type as usize)), "{} repeats an earlier position", index);
+
+            let files = |wanted: PieceType| -> Vec<usize> { (0..8).filter(|&file| rank[file] == wanted).collect() };
+            let bishops = files(PieceType::Bishop);
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}: bishops on the same color", index);
+            let rooks = files(PieceType::Rook);
+            let king = files(PieceType::King)[0];
+            assert!(rooks[0] < king && king < rooks[1], "{}: king not between the rooks", index);
+
+            // Whatever the setup, the FEN round trip has to keep it, castling rights included
+            let game = Game::chess960(index).unwrap();
+            let again = Game::read_FEN(&game.to_FEN()).unwrap();
+            assert!(again == game, "{}", game.to_FEN());
+            let again = Game::read_FEN(&game.to_shredder_FEN()).unwrap();
+            assert!(again == game, "{}", game.to_shredder_FEN());
+        }
+    }
+
+    #[test]
+    fn castling_rights_in_fen() {
+        // Shredder-FEN names the rook files, X-FEN only does so when K/Q would point at the wrong rook
+        let game = Game::read_FEN("rk2r3/8/8/8/8/8/8/RK2R2R w HAea - 0 1").unwrap();
+        assert!(game.chess960);
+        assert_eq!(game.castling_rooks, [[7, 0], [60, 56]]);
+        assert_eq!(game.to_FEN(), "rk2r3/8/8/8/8/8/8/RK2R2R w KQkq - 0 1");
+
+        let game = Game::read_FEN("rk2r3/8/8/8/8/8/8/RK2R2R w EAea - 0 1").unwrap();
+        assert_eq!(game.castling_rooks[0], [4, 0]);
+        assert_eq!(game.to_FEN(), "rk2r3/8/8/8/8/8/8/RK2R2R w EQkq - 0 1");
+        assert_eq!(game.to_shredder_FEN(), "rk2r3/8/8/8/8/8/8/RK2R2R w EAea - 0 1");
+
+        // A normal game keeps writing KQkq
+        assert_eq!(Game::initialize().to_shredder_FEN(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1");
+        assert!(!Game::read_FEN(STARTING_FEN).unwrap().chess960);
+        assert!(Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1").unwrap() == Game::initialize());
+
+        // The king's own file can't be a castling right
+        assert!(Game::read_FEN("rk2r3/8/8/8/8/8/8/RK2R2R w B - 0 1").is_err());
+    }
+
+    fn play(game: &mut Game, coordinates: &str) {
+        let m = game.find_legal_move(coordinates).unwrap();
+        game.make_move(m);
+    }
+
+    #[test]
+    fn castling_onto_the_rooks_square() {
+        // King on f1 and rook on g1: castling kingside swaps them
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/4RKR1 w G - 0 1").unwrap();
+        play(&mut game, "f1g1");
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/4RRK1 b - - 1 1");
+        assert_eq!(game.hash, game.compute_hash());
+        game.unmake_move();
+        // The g1 rook is the outermost one on the king's right, so X-FEN calls that right K
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/4RKR1 w K - 0 1");
+
+        // King already on g1: only the rook moves
+        let mut game = Game::read_FEN("1r4kr/8/8/8/8/8/8/1R4KR b hb - 0 1").unwrap();
+        play(&mut game, "g8h8");
+        assert_eq!(game.to_FEN(), "1r3rk1/8/8/8/8/8/8/1R4KR w - - 1 2");
+
+        // King on b1, rook on a1: queenside the king moves right, past the squares it has to keep empty
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        play(&mut game, "b1a1");
+        assert_eq!(game.to_FEN(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+    }
+
+    #[test]
+    fn castling_rules() {
+        // The knight on d1 is in the way of the rook's trip from a1 to d1, though not of the king's
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/R1KN4 w A - 0 1").unwrap();
+        assert!(game.legal_moves().iter().all(|m| m.kind != MoveKind::QueensideCastle));
+
+        // The rook on f8 covers f1, which the king crosses on the way from e1 to g1
+        let game = Game::read_FEN("4kr2/8/8/8/8/8/8/4K2R w H - 0 1").unwrap();
+        assert!(game.legal_moves().iter().all(|m| m.kind != MoveKind::KingsideCastle));
+
+        // The castling rook shields the king from the a1 rook before castling, but not after
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/rRK5 w B - 0 1").unwrap();
+        assert!(game.legal_moves().iter().all(|m| m.kind != MoveKind::QueensideCastle));
+
+        // Moving the rook that belongs to a right only loses that right
+        let mut game = Game::chess960(0).unwrap();
+        play(&mut game, "h2h4");
+        play(&mut game, "a7a6");
+        play(&mut game, "h1h3");
+        assert_eq!(game.castling_rights, CastlingRights::ALL - CastlingRights::WHITEKINGSIDE);
+    }
+
+    // Reference numbers from https://www.chessprogramming.org/Chess960_Perft_Results
+    #[test]
+    fn perft_chess960() {
+        for (fen, expected) in [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", [21, 528, 12189, 326672]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", [21, 807, 18002, 667366]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", [20, 479, 10471, 273318])
+        ] {
+            let mut game = Game::read_FEN(fen).unwrap();
+            for (depth, &nodes) in expected.iter().enumerate() {
+                assert_eq!(perft::perft(&mut game, depth + 1), nodes, "{} at depth {}", fen, depth + 1);
+            }
+        }
+    }
+}
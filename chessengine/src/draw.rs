@@ -0,0 +1,218 @@
+/*
+    /////////////
+    /// Draws ///
+    /////////////
+
+    Besides stalemate, a game can be drawn because:
+    - the same position occurred three times (same pieces on the same squares, same side to move, same castling rights and
+      en passant possibilities). Either player may then claim a draw; after five times the game is drawn automatically.
+    - 50 moves by each side (100 half moves) went by without a capture or pawn move. Again a player may claim it, and after
+      75 moves it is automatic. If the move that reached the limit gave mate, the mate counts.
+    - nobody can possibly mate anymore: king against king, king and a single knight or bishop against a king, or only
+      bishops left that all stand on squares of the same color.
+
+    Repetitions are found with the Zobrist keys (see zobrist.rs) that `make_move` saves in `Game.history`. Only positions
+    since the last capture or pawn move (the last `halfmove_clock` plies) can be repetitions, since those moves can't be
+    taken back, and only every other one of those has the same side to move.
+*/
+
+use crate::{Color, Game, PieceType};
+
+// a1 is a dark square, and squares alternate along every rank and file: b1, d1, ..., a2, c2, ... are the light ones
+const LIGHT_SQUARES: u64 = 0x55aa_55aa_55aa_55aa;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DrawReason {
+    Stalemate,
+    InsufficientMaterial,
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    ThreefoldRepetition,
+    FiftyMoveRule
+}
+
+impl DrawReason {
+    // Threefold repetition and the fifty move rule only end the game once a player claims the draw
+    pub fn is_automatic(self) -> bool {
+        !matches!(self, DrawReason::ThreefoldRepetition | DrawReason::FiftyMoveRule)
+    }
+}
+
+impl Game {
+
+    // How often the current position has occurred so far, counting the current occurrence.
+    pub fn repetition_count(&self) -> usize {
+        let earlier = self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock)
+            .skip(1) // the position one ply back had the other side to move
+            .step_by(2)
+            .filter(|undo| undo.hash() == self.hash)
+            .count();
+
+        earlier + 1
+    }
+
+    // Has the current position occurred before? The search treats that as a draw already: if repeating the position was
+    // good enough once, nothing stops the players from repeating it again.
+    pub fn is_repetition(&self) -> bool {
+        self.repetition_count() > 1
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 3
+    }
+
+    pub fn is_fivefold_repetition(&self) -> bool {
+        self.repetition_count() >= 5
+    }
+
+    pub fn is_fifty_move_rule(&self) -> bool {
+        self.halfmove_clock >= 100 && !self.is_checkmate()
+    }
+
+    pub fn is_seventy_five_move_rule(&self) -> bool {
+        self.halfmove_clock >= 150 && !self.is_checkmate()
+    }
+
+    pub fn has_insufficient_material(&self) -> bool {
+        let boards = self.bitboards();
+        let mut knights = 0;
+        let mut bishops = 0;
+        for color in [Color::White, Color::Black] {
+            let ours = &boards[color as usize];
+            if ours[PieceType::Pawn as usize] | ours[PieceType::Rook as usize] | ours[PieceType::Queen as usize] != 0 {
+                return false;
+            }
+            knights += ours[PieceType::Knight as usize].count_ones();
+            bishops |= ours[PieceType::Bishop as usize];
+        }
+
+        let light_bishops = (bishops & LIGHT_SQUARES).count_ones();
+        let dark_bishops = (bishops & !LIGHT_SQUARES).count_ones();
+
+        match knights {
+            // Bishops (of either side) that can never reach the other color can't trap a king
+            0 => light_bishops == 0 || dark_bishops == 0,
+            1 => light_bishops + dark_bishops == 0,
+            _ => false
+        }
+    }
+
+    // Why the game is (or can be claimed) drawn right now, if it is. Automatic draws are reported before claimable ones.
+    pub fn draw_reason(&self) -> Option<DrawReason> {
+        if self.is_stalemate() {
+            Some(DrawReason::Stalemate)
+        } else if self.has_insufficient_material() {
+            Some(DrawReason::InsufficientMaterial)
+        } else if self.is_fivefold_repetition() {
+            Some(DrawReason::FivefoldRepetition)
+        } else if self.is_seventy_five_move_rule() {
+            Some(DrawReason::SeventyFiveMoveRule)
+        } else if self.is_threefold_repetition() {
+            Some(DrawReason::ThreefoldRepetition)
+        } else if self.is_fifty_move_rule() {
+            Some(DrawReason::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &str) {
+        for coordinates in moves.split_whitespace() {
+            let m = game.find_legal_move(coordinates).unwrap();
+            game.make_move(m);
+        }
+    }
+
+    #[test]
+    fn counts_repetitions() {
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        assert_eq!(game.repetition_count(), 1);
+
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.repetition_count(), 2);
+        assert!(game.is_repetition());
+        assert!(!game.is_threefold_repetition());
+
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8");
+        assert!(game.is_threefold_repetition());
+        assert_eq!(game.draw_reason(), Some(DrawReason::ThreefoldRepetition));
+        assert!(!game.draw_reason().unwrap().is_automatic());
+
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8");
+        assert!(game.is_fivefold_repetition());
+        assert_eq!(game.draw_reason(), Some(DrawReason::FivefoldRepetition));
+        assert!(game.draw_reason().unwrap().is_automatic());
+
+        // A pawn move can't be undone, so nothing before it can repeat anymore
+        play(&mut game, "e2e4");
+        assert_eq!(game.repetition_count(), 1);
+    }
+
+    #[test]
+    fn repetitions_need_the_same_rights() {
+        // Same pieces, but the king walk costs white the castling rights
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play(&mut game, "e1f1 e8f8 f1e1 f8e8");
+        assert_eq!(game.repetition_count(), 1);
+        play(&mut game, "e1f1 e8f8 f1e1 f8e8");
+        assert_eq!(game.repetition_count(), 2);
+    }
+
+    #[test]
+    fn move_rules() {
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert_eq!(game.draw_reason(), None);
+
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").unwrap();
+        assert_eq!(game.draw_reason(), Some(DrawReason::FiftyMoveRule));
+
+        let game = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 150 105").unwrap();
+        assert_eq!(game.draw_reason(), Some(DrawReason::SeventyFiveMoveRule));
+
+        // Mate on the hundredth half move still wins
+        let game = Game::read_FEN("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").unwrap();
+        assert!(game.is_checkmate());
+        assert!(!game.is_fifty_move_rule());
+        assert_eq!(game.draw_reason(), None);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1", // K v K
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1", // K+N v K
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", // K+B v K
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", // bishops on c1 and f8, both dark squares
+            "4k3/8/8/8/8/8/8/B1B1K1B1 b - - 0 1" // three dark squared bishops
+        ] {
+            let game = Game::read_FEN(fen).unwrap();
+            assert!(game.has_insufficient_material(), "{}", fen);
+            assert_eq!(game.draw_reason(), Some(DrawReason::InsufficientMaterial));
+        }
+
+        for fen in [
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", // a pawn can still promote
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1", // c1 is dark, c8 is light
+            "4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", // two knights
+            "4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1" // knight against bishop
+        ] {
+            let game = Game::read_FEN(fen).unwrap();
+            assert!(!game.has_insufficient_material(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn stalemate_is_a_draw() {
+        let game = Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(game.draw_reason(), Some(DrawReason::Stalemate));
+    }
+}
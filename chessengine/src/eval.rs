@@ -0,0 +1,257 @@
+/*
+    ///////////////////////
+    /// Evaluation      ///
+    ///////////////////////
+
+    A static guess of how good a position is, in centipawns (100 = one pawn), from the point of view of the side to move.
+
+    Two ingredients:
+    1. Material: every piece is worth a fixed amount.
+    2. Piece-square tables: a small bonus or penalty depending on where a piece stands. Knights like the center, pawns like
+       to advance, the king likes to hide behind its pawns while there is still material around, and likes the center
+       once the queens and rooks are gone.
+
+    The tables are taken from Tomasz Michniewski's "Simplified Evaluation Function":
+    https://www.chessprogramming.org/Simplified_Evaluation_Function
+
+    They are written from white's point of view and indexed exactly like our squares (see `index_to_position`):
+    the first row of numbers is rank 1 (a1..h1), the last row is rank 8. A black piece on square `s` uses the entry of the
+    vertically mirrored square `s ^ 56` (a8 <-> a1, e7 <-> e2, ...), so both colors share the same tables.
+*/
+
+use crate::{pop_lowest_bit, Color, Game, PieceType};
+
+// In the order of their `PieceType` discriminants, i.e. the order of a color's bitboards.
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King
+];
+
+pub fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20000
+    }
+}
+
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,   5,  10,  25,  25,  10,   5,   5,
+     10,  10,  20,  30,  30,  20,  10,  10,
+     50,  50,  50,  50,  50,  50,  50,  50,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+      0,   0,   0,   5,   5,   0,   0,   0,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      5,  10,  10,  10,  10,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+     20,  30,  10,   0,   0,  10,  30,  20,
+     20,  20,   0,   0,   0,   0,  20,  20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -50, -40, -30, -20, -20, -30, -40, -50
+];
+
+// How much non-pawn material is left, from 24 (everything, as in the starting position) down to 0 (only kings and pawns).
+// Used to blend the king's middlegame table into its endgame table as pieces come off.
+const MAX_PHASE: i32 = 24;
+
+fn phase_weight(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Knight | PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 4,
+        PieceType::Pawn | PieceType::King => 0
+    }
+}
+
+// The table entry for a piece of `color` on `square`
+fn table_square(square: usize, color: Color) -> usize {
+    match color {
+        Color::White => square,
+        Color::Black => square ^ 56
+    }
+}
+
+pub fn evaluate(game: &Game) -> i32 {
+    let mut phase = 0;
+    for boards in game.bitboards() {
+        for (piece_type, board) in PIECE_TYPES.into_iter().zip(boards) {
+            phase += phase_weight(piece_type) * board.count_ones() as i32;
+        }
+    }
+    let phase = phase.min(MAX_PHASE);
+
+    let mut score = 0;
+    let mut occupied = game.occupied;
+    while occupied != 0 {
+        let square = pop_lowest_bit(&mut occupied);
+        let piece = game.mailbox[square].expect("an occupied square holds a piece");
+        let square = table_square(square, piece.color);
+        let positional = match piece.piece_type {
+            PieceType::Pawn => PAWN_TABLE[square],
+            PieceType::Knight => KNIGHT_TABLE[square],
+            PieceType::Bishop => BISHOP_TABLE[square],
+            PieceType::Rook => ROOK_TABLE[square],
+            PieceType::Queen => QUEEN_TABLE[square],
+            PieceType::King => {
+                (KING_MIDDLEGAME_TABLE[square] * phase + KING_ENDGAME_TABLE[square] * (MAX_PHASE - phase)) / MAX_PHASE
+            }
+        };
+
+        let value = piece_value(piece.piece_type) + positional;
+        score += if piece.color == Color::White { value } else { -value };
+    }
+
+    if game.active_color == Color::White { score } else { -score }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same position with the colors swapped: board flipped upside down, uppercase <-> lowercase, other side to move.
+    fn color_flipped(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|ch| if ch.is_ascii_uppercase() { ch.to_ascii_lowercase() } else { ch.to_ascii_uppercase() })
+                .collect()
+        };
+
+        let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let color = if fields[1] == "w" { "b" } else { "w" };
+
+        // Castling letters keep the KQkq order
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|&ch| "KQkq-".find(ch));
+        let castling: String = castling.into_iter().collect();
+
+        let en_passant = match fields[3] {
+            "-" => "-".to_string(),
+            square => square.chars().map(|ch| match ch { '3' => '6', '6' => '3', other => other }).collect()
+        };
+
+        format!("{} {} {} {} {} {}", placement.join("/"), color, castling, en_passant, fields[4], fields[5])
+    }
+
+    const POSITIONS: [&str; 6] = [
+        crate::STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "8/8/4k3/8/2p5/8/B2K4/8 b - - 0 50"
+    ];
+
+    #[test]
+    fn color_flipped_positions_evaluate_the_same() {
+        for fen in POSITIONS {
+            let flipped = color_flipped(fen);
+            let original = Game::read_FEN(fen).unwrap();
+            let mirrored = Game::read_FEN(&flipped).unwrap();
+            assert_eq!(evaluate(&original), evaluate(&mirrored), "{} vs {}", fen, flipped);
+        }
+    }
+
+    #[test]
+    fn starting_position_is_balanced() {
+        let game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        assert_eq!(evaluate(&game), 0);
+    }
+
+    #[test]
+    fn score_is_from_the_side_to_moves_point_of_view() {
+        // White is a rook up
+        let white_to_move = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let black_to_move = Game::read_FEN("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert!(evaluate(&white_to_move) > 400);
+        assert_eq!(evaluate(&white_to_move), -evaluate(&black_to_move));
+    }
+
+    #[test]
+    fn pieces_prefer_good_squares() {
+        // A knight in the center beats a knight in the corner, an advanced pawn beats one at home
+        let center = Game::read_FEN("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        let corner = Game::read_FEN("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+        assert!(evaluate(&center) > evaluate(&corner));
+
+        let advanced = Game::read_FEN("4k3/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let home = Game::read_FEN("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert!(evaluate(&advanced) > evaluate(&home));
+    }
+}
@@ -0,0 +1,323 @@
+/*
+    //////////////////////
+    /// Magic bitboards ///
+    //////////////////////
+
+    Rooks, bishops and queens attack along rays until something blocks them, so unlike knights their attacks depend on the
+    rest of the board. Walking the rays square by square (`slider_attacks` below) works, but move generation and check
+    detection ask for these attacks millions of times per second, and the walk is where the time goes.
+
+    Magic bitboards turn the walk into a table lookup:
+    1. For every square only a few squares can actually block: the ones on the piece's rays, minus the board edge (a piece
+       on the edge blocks nothing, the ray ends there anyway). That set is the square's `mask`, at most 12 squares for a
+       rook and 9 for a bishop.
+    2. Every blocker arrangement `occupied & mask` is multiplied by a "magic" number. The magic is chosen so that the top
+       `bits` bits of the product are different for any two arrangements that give different attacks. Those bits are the
+       index into a table holding the precomputed attacks.
+    3. Magics are found by trial and error: random numbers with few bits set are tried until one maps every arrangement
+       without a harmful collision. That search takes a while, so it was done once and its results are the two tables
+       below. `cargo test -- --ignored find_magics` runs it again (see the tests at the bottom). Filling the attack tables
+       from the magics is quick and happens the first time an attack is asked for.
+
+    More on this: https://www.chessprogramming.org/Magic_Bitboards
+*/
+
+use std::sync::OnceLock;
+
+use crate::PiecePosition;
+
+// Directions are written as (file step, rank step).
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// Sliding pieces keep walking in a direction until they fall off the board or bump into something.
+// The square they bump into is still attacked (it might be an enemy piece to capture), so it is added before stopping.
+// This is the slow way; it is only used to fill in the magic tables.
+fn slider_attacks(square: usize, occupied: PiecePosition, directions: &[(i32, i32); 4]) -> PiecePosition {
+    let mut attacks = 0;
+    let file = (square % 8) as i32;
+    let rank = (square / 8) as i32;
+
+    for &(file_step, rank_step) in directions {
+        let mut f = file + file_step;
+        let mut r = rank + rank_step;
+        while (0..8).contains(&f) && (0..8).contains(&r) {
+            let bit: PiecePosition = 1 << (r * 8 + f);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+            f += file_step;
+            r += rank_step;
+        }
+    }
+
+    attacks
+}
+
+// The squares that can block a slider on `square`: every square on its rays except the last one of each ray.
+fn blocker_mask(square: usize, directions: &[(i32, i32); 4]) -> PiecePosition {
+    let mut mask = 0;
+    let file = (square % 8) as i32;
+    let rank = (square / 8) as i32;
+
+    for &(file_step, rank_step) in directions {
+        let mut f = file + file_step;
+        let mut r = rank + rank_step;
+        while (0..8).contains(&(f + file_step)) && (0..8).contains(&(r + rank_step)) {
+            mask |= 1 << (r * 8 + f);
+            f += file_step;
+            r += rank_step;
+        }
+    }
+
+    mask
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SquareMagic {
+    mask: PiecePosition,
+    magic: u64,
+    shift: u32,
+    offset: usize // where this square's part of `MagicTables.attacks` starts
+}
+
+impl SquareMagic {
+    fn index(&self, occupied: PiecePosition) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [SquareMagic; 64],
+    bishop: [SquareMagic; 64],
+    attacks: Vec<PiecePosition> // the attack tables of all 128 square/piece combinations, one after the other
+}
+
+#[rustfmt::skip]
+const ROOK_MAGICS: [u64; 64] = [
+    0x0080001020804000, 0x0140011000200040, 0x2080081000200081, 0x0480100080842801,
+    0x0080080080040002, 0x0100020400080100, 0x5280220031000080, 0x0200008203284d04,
+    0x0048800040068021, 0x0002400040201000, 0x0086801000a00080, 0x1000808008001000,
+    0x0240800402800801, 0x0100800200040080, 0x004a000200048108, 0x2002000100805402,
+    0x42a0208000804000, 0x2a00404000201001, 0x0040410020010812, 0x2010808010000801,
+    0x1010818004002800, 0x0002008004000280, 0x8000040082011008, 0x2a001a0001054384,
+    0x8404400380022090, 0xe0100040c0112000, 0x0110088080102000, 0x2408080080801000,
+    0x0000108500080100, 0x0002000200041008, 0x0000820400010890, 0x0000004200010084,
+    0x0000400082800023, 0x001000e003400240, 0x1002001042002081, 0x0088100080800801,
+    0x0428010881801400, 0x0082000400800280, 0x009a000100404080, 0x0410006c02000091,
+    0x808000412000c000, 0x0040008420028040, 0x0400208600460010, 0x4340080010008080,
+    0x0a21000802110004, 0x0094040002008080, 0x0012006881020004, 0x2101000040810002,
+    0xc00100800c412900, 0x0000400021009300, 0x6020050020441100, 0x4012080090008380,
+    0x0112040008028080, 0x4022001008040200, 0x2808480201900400, 0x0000210040940200,
+    0x0000800102146041, 0x08410030844202a2, 0x0840200041002ad3, 0x045101a030006409,
+    0x100200082090540e, 0x0226000130046842, 0x0008008841021004, 0x4200064021041482
+];
+
+#[rustfmt::skip]
+const BISHOP_MAGICS: [u64; 64] = [
+    0xbe2029450a048200, 0x14481000c0810200, 0x0822008902000005, 0x0011041084142008,
+    0x822c0520080c0024, 0x2000901008000202, 0x1044088210121008, 0x0080240400984800,
+    0x0094041002084100, 0x0020028829040180, 0x048610810e102008, 0x800004104a020106,
+    0x20100e06100000c2, 0x8000409004200020, 0x9021020802821020, 0x02000500909c2012,
+    0xc008084420080a04, 0x0002800424040400, 0x190810040044000c, 0x000411a201220000,
+    0x0104006a11200025, 0x1482422200422000, 0x0889000841101052, 0xd000458a01108810,
+    0x0028c24034240800, 0x0010090882980101, 0x0000280450008020, 0x0411080224004010,
+    0x2010840100802000, 0x000a060040889000, 0xc492040008411880, 0x42484204208a0100,
+    0x8001484000081008, 0x10c1086004028424, 0x00c1402080300501, 0x8000020080080080,
+    0x0050120020220082, 0x2860440100102082, 0x91a1010900020808, 0x02080a8380842202,
+    0x8002020240502080, 0x0016013002002800, 0x0002101088001001, 0x8020014208008080,
+    0x0a8140a810410200, 0x0008010800200200, 0x000210a400863108, 0x0002080208800030,
+    0x8000820820850081, 0x5220440084101000, 0x0037010051100208, 0x0000040020880280,
+    0x4100000420820002, 0x08101020090d0c00, 0x0021a40908010c0a, 0xa210501480819002,
+    0x0805028750023000, 0x006c020052021020, 0x0080012894008802, 0xc000943101048800,
+    0x0800084024a50400, 0x02a2080421040102, 0x0460410801010200, 0x8108600404003020
+];
+
+// Every subset of `mask`, starting with the empty one ("carry rippler" enumeration)
+fn subsets(mask: PiecePosition) -> impl Iterator<Item = PiecePosition> {
+    let mut next: Option<PiecePosition> = Some(0);
+    std::iter::from_fn(move || {
+        let subset = next?;
+        let following = subset.wrapping_sub(mask) & mask;
+        next = (following != 0).then_some(following);
+        Some(subset)
+    })
+}
+
+// Fills in the attack table of one square and appends it to `attacks`.
+fn square_magic(
+    square: usize,
+    directions: &[(i32, i32); 4],
+    magic: u64,
+    attacks: &mut Vec<PiecePosition>
+) -> SquareMagic {
+    let mask = blocker_mask(square, directions);
+    let bits = mask.count_ones();
+    let square_magic = SquareMagic { mask, magic, shift: 64 - bits, offset: attacks.len() };
+
+    attacks.resize(attacks.len() + (1 << bits), 0);
+    for occupied in subsets(mask) {
+        let index = square_magic.index(occupied);
+        let attack = slider_attacks(square, occupied, directions);
+        // Two arrangements may share a slot, but only if they lead to the same attacks
+        assert!(attacks[index] == 0 || attacks[index] == attack, "bad magic for square {}", square);
+        attacks[index] = attack;
+    }
+
+    square_magic
+}
+
+fn build_tables() -> MagicTables {
+    let mut tables = MagicTables {
+        rook: [SquareMagic::default(); 64],
+        bishop: [SquareMagic::default(); 64],
+        attacks: Vec::new()
+    };
+
+    for square in 0..64 {
+        tables.rook[square] = square_magic(square, &ROOK_DIRECTIONS, ROOK_MAGICS[square], &mut tables.attacks);
+        tables.bishop[square] = square_magic(square, &BISHOP_DIRECTIONS, BISHOP_MAGICS[square], &mut tables.attacks);
+    }
+
+    tables
+}
+
+static TABLES: OnceLock<MagicTables> = OnceLock::new();
+
+fn tables() -> &'static MagicTables {
+    TABLES.get_or_init(build_tables)
+}
+
+// Builds the tables right away, so that the first search doesn't have to wait for them.
+pub fn init() {
+    tables();
+}
+
+pub fn rook_attacks(square: usize, occupied: PiecePosition) -> PiecePosition {
+    let tables = tables();
+    tables.attacks[tables.rook[square].index(occupied)]
+}
+
+pub fn bishop_attacks(square: usize, occupied: PiecePosition) -> PiecePosition {
+    let tables = tables();
+    tables.attacks[tables.bishop[square].index(occupied)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift64*, a small random number generator. A fixed seed keeps the magics (and so the tables) the same on every run.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        // Magics with only a few bits set work much better, AND-ing three numbers leaves about 8 of them.
+        fn sparse(&mut self) -> u64 {
+            self.next() & self.next() & self.next()
+        }
+    }
+
+    // Tries random magics for one square until one works.
+    fn find_magic(square: usize, directions: &[(i32, i32); 4], random: &mut Random) -> u64 {
+        let mask = blocker_mask(square, directions);
+        let bits = mask.count_ones();
+
+        let occupancies: Vec<(PiecePosition, PiecePosition)> =
+            subsets(mask).map(|subset| (subset, slider_attacks(square, subset, directions))).collect();
+
+        let size = 1 << bits;
+        let mut table = vec![0; size];
+        // Which attempt last wrote a slot, so the table doesn't have to be cleared for every candidate
+        let mut written_in = vec![0u32; size];
+
+        for attempt in 1u32.. {
+            let magic = random.sparse();
+            // A quick filter: the mask has to spread out over the top bits or the indices will be crammed together
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+
+            let candidate = SquareMagic { mask, magic, shift: 64 - bits, offset: 0 };
+            let fits = occupancies.iter().all(|&(occupied, attack)| {
+                let index = candidate.index(occupied);
+                if written_in[index] != attempt {
+                    written_in[index] = attempt;
+                    table[index] = attack;
+                    true
+                } else {
+                    // Two arrangements sharing a slot is fine, as long as they lead to the same attacks
+                    table[index] == attack
+                }
+            });
+
+            if fits {
+                return magic;
+            }
+        }
+
+        unreachable!("ran out of attempts looking for a magic")
+    }
+
+    // Prints a fresh pair of magic tables. Slow, hence ignored unless asked for.
+    #[test]
+    #[ignore]
+    fn find_magics() {
+        let mut random = Random(0x0123_4567_89ab_cdef);
+        let mut rook = Vec::new();
+        let mut bishop = Vec::new();
+        for square in 0..64 {
+            rook.push(format!("{:#018x}", find_magic(square, &ROOK_DIRECTIONS, &mut random)));
+            bishop.push(format!("{:#018x}", find_magic(square, &BISHOP_DIRECTIONS, &mut random)));
+        }
+        println!("ROOK_MAGICS: {}", rook.join(", "));
+        println!("BISHOP_MAGICS: {}", bishop.join(", "));
+    }
+
+    #[test]
+    fn masks_leave_out_the_edges() {
+        // Rook on a1: a2..a7 and b1..g1
+        assert_eq!(blocker_mask(0, &ROOK_DIRECTIONS).count_ones(), 12);
+        // Rook on d4: 3 + 2 squares along the rank, 2 + 3 along the file
+        assert_eq!(blocker_mask(27, &ROOK_DIRECTIONS).count_ones(), 10);
+        // Bishop on d4: the long diagonals minus the corners
+        assert_eq!(blocker_mask(27, &BISHOP_DIRECTIONS).count_ones(), 9);
+        assert_eq!(blocker_mask(0, &BISHOP_DIRECTIONS), slider_attacks(0, 0, &BISHOP_DIRECTIONS) & !(1 << 63));
+    }
+
+    #[test]
+    fn lookups_match_the_ray_walk() {
+        let mut random = Random(42);
+        for square in 0..64 {
+            for _ in 0..200 {
+                // Random boards with varying numbers of pieces on them
+                let occupied = match random.next() % 3 {
+                    0 => random.sparse(),
+                    1 => random.next() & random.next(),
+                    _ => random.next()
+                };
+                assert_eq!(rook_attacks(square, occupied), slider_attacks(square, occupied, &ROOK_DIRECTIONS));
+                assert_eq!(bishop_attacks(square, occupied), slider_attacks(square, occupied, &BISHOP_DIRECTIONS));
+            }
+        }
+    }
+
+    #[test]
+    fn every_blocker_arrangement_is_covered() {
+        for square in 0..64 {
+            for occupied in subsets(blocker_mask(square, &ROOK_DIRECTIONS)) {
+                assert_eq!(rook_attacks(square, occupied), slider_attacks(square, occupied, &ROOK_DIRECTIONS));
+            }
+            for occupied in subsets(blocker_mask(square, &BISHOP_DIRECTIONS)) {
+                assert_eq!(bishop_attacks(square, occupied), slider_attacks(square, occupied, &BISHOP_DIRECTIONS));
+            }
+        }
+    }
+}
@@ -13,10 +13,40 @@
 */
 
 use bitflags::bitflags;
-use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::time::Instant;
+
+mod book;
+mod chess960;
+mod draw;
+mod eval;
+mod magic;
+mod makemove;
+mod movegen;
+mod ordering;
+mod perft;
+mod pgn;
+mod play;
+mod render;
+mod san;
+mod search;
+mod timeman;
+mod tt;
+mod uci;
+mod zobrist;
+
+use makemove::Undo;
 
 type PiecePosition = u64; // setting an alias for u64
 
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// One bitboard per (color, piece type) pair, indexed as `boards[color as usize][piece_type as usize]`.
+// `Game` keeps twelve of these, next to the occupancy boards and the mailbox (see `Game` below).
+type Bitboards = [[PiecePosition; 6]; 2];
+
 /*
     In Rust, #[derive(Trait)] is a syntax for automatically implementing certain traits for a type. A trait is a Rust language feature that defines a set of methods that a type can implement.
     The derive attribute allows you to automatically implement one or more traits for a type, without having to write the implementation manually.
@@ -26,13 +56,22 @@ type PiecePosition = u64; // setting an alias for u64
     The PartialEq trait is a built-in trait that allows a type to be compared for equality using the == and != operators. This is useful for testing and other situations where you need to compare values for equality.
 */
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 enum Color {
     White, 
     Black
 }
 
-#[derive(Debug, PartialEq)]
+impl Color {
+    fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 enum PieceType {
     Pawn, 
     Rook,
@@ -42,19 +81,13 @@ enum PieceType {
     King
 }
 
-#[derive(Debug, PartialEq)]
+// What stands on a square. Where it stands is known from the square (or bitboard) the piece was found on.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 struct Piece {
-    position: PiecePosition,
     color: Color,
     piece_type: PieceType
 }
 
-#[derive(Debug)]
-enum Square {
-    Empty,
-    Occupied(usize), // This usize is used to denote the piece index.
-}
-
 bitflags! {
     struct CastlingRights: u8 {
         const NONE = 0;
@@ -69,345 +102,447 @@ bitflags! {
     }
 }
 
+// The two ways to castle: towards the h-file or towards the a-file. Used to index `Game.castling_rooks`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum CastlingSide {
+    Kingside,
+    Queenside
+}
+
+impl CastlingRights {
+    // The single right `color` has to castle towards `side`
+    fn of(color: Color, side: CastlingSide) -> CastlingRights {
+        match (color, side) {
+            (Color::White, CastlingSide::Kingside) => CastlingRights::WHITEKINGSIDE,
+            (Color::White, CastlingSide::Queenside) => CastlingRights::WHITEQUEENSIDE,
+            (Color::Black, CastlingSide::Kingside) => CastlingRights::BLACKKINGSIDE,
+            (Color::Black, CastlingSide::Queenside) => CastlingRights::BLACKQUEENSIDE
+        }
+    }
+}
+
+// Where the rooks castling rights refer to start out in normal chess: h1, a1, h8 and a8.
+const STANDARD_CASTLING_ROOKS: [[usize; 2]; 2] = [[7, 0], [63, 56]];
+
+// The six space separated fields of a FEN string, in the order they appear.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FenField {
+    PiecePlacement,
+    ActiveColor,
+    CastlingRights,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum FenErrorKind {
+    MissingField,
+    InvalidCharacter(char),
+    InvalidValue(String), // the whole field is wrong, e.g. "x" as the color to move or "e5" as the en passant square
+    RankCount(usize), // the piece placement has this many ranks instead of 8
+    RowLength { rank: usize, squares: usize }, // this rank describes this many squares instead of 8
+    PawnOnBackRank(usize), // a pawn on rank 1 or 8, where it could never be (and would have nowhere to go)
+    TrailingInput(String) // something follows the fullmove number
+}
+
+// What `read_FEN` hands back when it can't make sense of a FEN string: which field is broken, the character offset
+// (counting from 0 at the start of the string) where the problem was found, and what exactly is wrong.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct FenError {
+    field: FenField,
+    offset: usize,
+    kind: FenErrorKind
+}
+
+impl FenError {
+    fn new(field: FenField, offset: usize, kind: FenErrorKind) -> FenError {
+        FenError { field, offset, kind }
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let field = match self.field {
+            FenField::PiecePlacement => "piece placement",
+            FenField::ActiveColor => "active color",
+            FenField::CastlingRights => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number"
+        };
+        write!(f, "Invalid FEN {} at character {}: ", field, self.offset)?;
+
+        match &self.kind {
+            FenErrorKind::MissingField => write!(f, "field is missing"),
+            FenErrorKind::InvalidCharacter(ch) => write!(f, "unexpected character '{}'", ch),
+            FenErrorKind::InvalidValue(value) => write!(f, "unexpected value \"{}\"", value),
+            FenErrorKind::RankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenErrorKind::RowLength { rank, squares } => write!(f, "rank {} describes {} squares instead of 8", rank, squares),
+            FenErrorKind::PawnOnBackRank(rank) => write!(f, "a pawn can't stand on rank {}", rank),
+            FenErrorKind::TrailingInput(input) => write!(f, "unexpected \"{}\" after the last field", input)
+        }
+    }
+}
+
 // Game type to own the data
 // Initially this was limited to just pieces and squares, but now having studied the FEN string for chessboard representation, we gotta add a few more fields
 // Full move: 1 move from white + 1 move from black
 // Half move: 1 move from any player
 // en_passant target square: This is a square over which a pawn has just passed while moving two squares
 // castling_rights: If neither side has the ability to castle, this field uses the character "-". Otherwise, this field contains one or more letters: "K" if White can castle kingside, "Q" if White can castle queenside, "k" if Black can castle kingside, and "q" if Black can castle queenside.
+/*
+    The board itself is stored twice over, since different questions are cheap to answer in different layouts:
+    - `boards`: one bitboard per color and piece type (12 in total). Move generation and attack detection work on these.
+    - `occupancy`: every square holding a white piece and every square holding a black piece, plus `occupied` for both.
+      These could be OR-ed together from `boards` each time, but they are needed so often that we keep them around.
+    - `mailbox`: what stands on each of the 64 squares, for the "what is on e4?" questions (making a move, SAN, printing).
+    All of them change together through `put_piece` and `take_piece` (see makemove.rs), which also keep `hash` in step.
+*/
+#[derive(Debug, Clone)]
 struct Game {
-    pieces: Vec<Piece>,
-    squares: Vec<Square>,
+    boards: Bitboards,
+    occupancy: [PiecePosition; 2], // indexed by color
+    occupied: PiecePosition,
+    mailbox: [Option<Piece>; 64],
     active_color: Color,
     castling_rights: CastlingRights,
+    castling_rooks: [[usize; 2]; 2], // the square of the rook each right belongs to, as `[color as usize][side as usize]`
+    chess960: bool, // castling follows the Chess960 rules, and castling moves are written as "king takes own rook"
     en_passant: Option<PiecePosition>, // Target Square. This is why it is an Option, since either you'll have a square or you won't
     halfmove_clock: usize, // The number of halfmoves since the last capture or pawn advance, used for the fifty-move rule
-    fullmove_number: usize //  The number of the full moves. It starts at 1 and is incremented after Black's move.
+    fullmove_number: usize, //  The number of the full moves. It starts at 1 and is incremented after Black's move.
+    hash: u64, // Zobrist key of the position (see zobrist.rs), kept up to date by `make_move`
+    history: Vec<Undo> // Everything `make_move` needs to remember so that `unmake_move` can put the position back exactly as it was
 }
 
 impl Game {
 
-    fn push_piece_and_square(
-        &mut self,
-        position: usize,
-        color: Color,
-        piece_type: PieceType,
-        index: &mut usize
-    ) {
-        self.pieces.push(
-            Piece{
-                position: (1 as u64) << position,
-                color: color,
-                piece_type: piece_type
-            }
-        );
-
-        self.squares.push(
-            Square::Occupied(*index)
-        );
-
-        *index += 1;
-    }
-    
-    // &mut self, since we are changing the struct `Game` itself with this function
-    fn push_empty_square(&mut self) {
-        self.squares.push(Square::Empty);
-    }
-
-    fn initialize() -> Game {
-        let mut game = Game {
-            pieces: vec![], 
-            squares: vec![],
+    // A board without any pieces on it, for `initialize` and `read_FEN` to fill in.
+    fn empty() -> Game {
+        Game {
+            boards: [[0; 6]; 2],
+            occupancy: [0; 2],
+            occupied: 0,
+            mailbox: [None; 64],
             active_color: Color::White,
             castling_rights: CastlingRights::ALL,
+            castling_rooks: STANDARD_CASTLING_ROOKS,
+            chess960: false,
             en_passant: None,
-            halfmove_clock: 1,
-            fullmove_number: 0
-        };
-        let mut piece_index = 0;
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            history: vec![]
+        }
+    }
 
-        let mut color = Color::White;
+    fn initialize() -> Game {
+        let mut game = Game::empty();
 
-        game.push_piece_and_square(
-            0,
-            color,
+        let back_rank = [
             PieceType::Rook,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            1,
-            color,
             PieceType::Knight,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            2,
-            color,
             PieceType::Bishop,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            3,
-            color,
             PieceType::Queen,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            4,
-            color,
             PieceType::King,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            5,
-            color,
             PieceType::Bishop,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            6,
-            color,
             PieceType::Knight,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            7,
-            color,
-            PieceType::Rook,
-            &mut piece_index
-        );
-
-        for i in 8..16 {
-            game.push_piece_and_square(
-                i, 
-                color,
-                PieceType::Pawn,
-                &mut piece_index
-            );
-        }
-
-        // Pushing the empty square in between the two opponents
-        for i in 16..48 {
-            game.push_empty_square();
-        }
+            PieceType::Rook
+        ];
 
-        color = Color::Black;
+        for (file, piece_type) in back_rank.into_iter().enumerate() {
+            game.put_piece(file, Piece { color: Color::White, piece_type });
+            game.put_piece(8 + file, Piece { color: Color::White, piece_type: PieceType::Pawn });
 
-        for i in 48..56 {
-            game.push_piece_and_square(
-                i, 
-                color,
-                PieceType::Pawn,
-                &mut piece_index
-            );
+            // Black mirrors white across the middle of the board
+            game.put_piece(48 + file, Piece { color: Color::Black, piece_type: PieceType::Pawn });
+            game.put_piece(56 + file, Piece { color: Color::Black, piece_type });
         }
 
-        let offset = 56;
-
-        game.push_piece_and_square(
-            0 + offset,
-            color,
-            PieceType::Rook,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            1 + offset,
-            color,
-            PieceType::Knight,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            2 + offset,
-            color,
-            PieceType::Bishop,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            3 + offset,
-            color,
-            PieceType::Queen,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            4 + offset,
-            color,
-            PieceType::King,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            5 + offset,
-            color,
-            PieceType::Bishop,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            6 + offset,
-            color,
-            PieceType::Knight,
-            &mut piece_index
-        );
-
-        game.push_piece_and_square(
-            7 + offset,
-            color,
-            PieceType::Rook,
-            &mut piece_index
-        );
-
+        game.hash = game.compute_hash();
         game
     }
 
-    fn to_string(&self) -> String {
-       let mut board = "".to_owned();
-       let mut temp = "".to_owned(); 
+    fn bitboards(&self) -> &Bitboards {
+        &self.boards
+    }
 
-       for (i, square) in self.squares.iter().enumerate() {
-        match square {
-            Square::Empty => temp.push_str(&index_to_position(i)),
-            Square::Occupied(idx) => temp.push_str(&self.pieces[*idx].to_string()),
-        }
-        
-        if(i + 1 ) % 8 == 0 {
-            temp.push_str("\n");
-            board.insert_str(0, &temp);
-            temp.clear();
-        }
-       }
-       // incase the if block was not activated
-       board.insert_str(0, &temp);
-       
-       board // return the value of board
+    // The piece standing on `square`, if any.
+    fn piece_at(&self, square: usize) -> Option<Piece> {
+        self.mailbox[square]
     }
 
     // This macro allows functions to have a non-snake case name
     #[allow(non_snake_case)]
-    fn read_FEN(fen: &str) -> Game {
-        let mut game = Game {
-            pieces: vec![],
-            squares: vec![],
-            active_color: Color::White,
-            castling_rights: CastlingRights::ALL,
-            en_passant: None,
-            halfmove_clock: 1,
-            fullmove_number: 0
-        };
+    fn read_FEN(fen: &str) -> Result<Game, FenError> {
+        let mut game = Game::empty();
 
-        let (position, rest) = split_on(fen, ' ');
-
-        let mut deque_squares = VecDeque::new();
-        let mut piece_index = 0;
-        let mut piece_position = 64;
-
-        // Now we want to loop over each row present in the FEN string rep of the game.
-        for row in position.splitn(8, '/' ) { // This will give you each row of the chess board.
-            // Checking if we are getting the split rows from the fen_string as expected or not
-            // println!("row: {}", row);
-
-            piece_position -= 8; //Every time a row is handeled
-            
-            // Since we would like to construct the GAME chess board out of this FEN string row, let's call a parse function for each row
-            let (pieces, squares) = parse_row(&row, piece_index, piece_position);
-
-            // Now the immediate problem that will pop up when you try to implement the `parse_row` function is that the output of rows after `splitn` looks
-            // something like this:
-            /*
-                row: rnbqkbnr
-                row: pppppppp
-                row: 8
-                row: 8
-                row: 8
-                row: 8
-                row: PPPPPPPP
-                row: RNBQKBNR
-            */
-            // So, when we start parsing the row from the for loop, we would be doing so in a order which is opposite to the numbering convention of the chessboard
-            // squares that we have been following. So, we need to introduce a data structure where we can input data from the front too (VecDeque) so that the first entry becomes the last in game.pieces and game.squares
-
-            // Now when you get the correctly inferred pieces and squares info from `parse_row`, let's put them in the `game`'s relevant vector.
-            for piece in pieces {
-                game.pieces.push(piece);
-                piece_index += 1;
-            }
+        // Every offset in a FenError counts characters, and as long as the string is plain ASCII that is the same as counting bytes.
+        if let Some((offset, ch)) = fen.chars().enumerate().find(|(_, ch)| !ch.is_ascii()) {
+            return Err(FenError::new(FenField::PiecePlacement, offset, FenErrorKind::InvalidCharacter(ch)));
+        }
+        let fen = fen.trim_end();
+        let mut rest = fen;
 
-            for sq in squares {
-                deque_squares.push_front(sq);
-            }
+        let (position_offset, position) = next_field(fen, &mut rest, FenField::PiecePlacement)?;
 
-            println!("row: {}", row);
+        let mut row_start = 64;
+        let mut row_offset = position_offset;
+
+        let rows: Vec<&str> = position.split('/').collect();
+        if rows.len() != 8 {
+            return Err(FenError::new(FenField::PiecePlacement, position_offset, FenErrorKind::RankCount(rows.len())));
         }
 
-        game.squares = Vec::from(deque_squares);
+        // FEN lists the ranks from 8 down to 1, while our squares count up from a1. So the first row starts at square 56,
+        // the next one at 48 and so on.
+        for row in rows {
+            row_start -= 8;
+            parse_row(&mut game, row, row_start, row_offset)?;
+            row_offset += row.len() + 1; // +1 for the '/'
+        }
 
-        let (color_to_move, rest) = split_on(rest, ' ');
+        let (offset, color_to_move) = next_field(fen, &mut rest, FenField::ActiveColor)?;
         game.active_color = match color_to_move {
             "w" => Color::White,
             "b" => Color::Black,
-            _ => panic!("Unknown color designator: {}", color_to_move)
+            _ => {
+                let kind = FenErrorKind::InvalidValue(color_to_move.to_string());
+                return Err(FenError::new(FenField::ActiveColor, offset, kind));
+            }
         };
 
-        let (castling_rights, rest) = split_on(rest, ' ');
+        let (offset, castling_rights) = next_field(fen, &mut rest, FenField::CastlingRights)?;
         let mut castling = CastlingRights::NONE;
-        for ch in castling_rights.chars() {
-            match ch {
-                'K' => castling |= CastlingRights::WHITEKINGSIDE,
-                'Q' => castling |= CastlingRights::WHITEQUEENSIDE,
-                'k' => castling |= CastlingRights::BLACKKINGSIDE,
-                'q' => castling |= CastlingRights::BLACKQUEENSIDE,
-                '-' => (),
-                _ => panic!("Invalid castling rights character: {}", ch)
-            };
+        if castling_rights != "-" {
+            for (i, ch) in castling_rights.char_indices() {
+                let color = if ch.is_ascii_uppercase() { Color::White } else { Color::Black };
+                let back_rank = if color == Color::White { 0 } else { 56 };
+                let king_file = game.home_king_file(color);
+                let rook_files = (game.boards[color as usize][PieceType::Rook as usize] >> back_rank) & 0xff;
+
+                // K and Q stand for the outermost rook on that side of the king (X-FEN). Without a rook there, the right
+                // is kept for the rook's usual corner. A file letter (Shredder-FEN) names the rook's file directly.
+                let (side, rook_file) = match ch.to_ascii_lowercase() {
+                    'k' => {
+                        let kingside_rooks = rook_files & !((2 << king_file) - 1);
+                        let file = if kingside_rooks == 0 { 7 } else { 63 - kingside_rooks.leading_zeros() as usize };
+                        (CastlingSide::Kingside, file)
+                    },
+                    'q' => {
+                        let queenside_rooks = rook_files & ((1 << king_file) - 1);
+                        let file = if queenside_rooks == 0 { 0 } else { queenside_rooks.trailing_zeros() as usize };
+                        (CastlingSide::Queenside, file)
+                    },
+                    letter @ 'a'..='h' if letter as usize - 'a' as usize != king_file => {
+                        let file = letter as usize - 'a' as usize;
+                        let side = if file > king_file { CastlingSide::Kingside } else { CastlingSide::Queenside };
+                        (side, file)
+                    },
+                    _ => return Err(FenError::new(FenField::CastlingRights, offset + i, FenErrorKind::InvalidCharacter(ch)))
+                };
+
+                castling |= CastlingRights::of(color, side);
+                game.castling_rooks[color as usize][side as usize] = back_rank + rook_file;
+                // A king or castling rook away from its usual square can only be Chess960
+                if king_file != 4 || rook_file != STANDARD_CASTLING_ROOKS[0][side as usize] {
+                    game.chess960 = true;
+                }
+            }
         }
 
         game.castling_rights = castling;
 
-        let (en_passant, rest) = split_on(rest, ' ');
+        let (offset, en_passant) = next_field(fen, &mut rest, FenField::EnPassant)?;
         match en_passant {
             "-" => game.en_passant = None,
-            s => match position_to_bit(s) {
-                Err(msg) => panic!("{}", msg),
-                Ok(bit) => game.en_passant = Some(bit)
+            s => {
+                // The target square is always right behind a pawn that just moved two squares: on rank 6 if white is
+                // to move (black just pushed), on rank 3 if black is to move. Anything else can't come from a real game.
+                let expected_rank = match game.active_color {
+                    Color::White => '6',
+                    Color::Black => '3'
+                };
+                match position_to_bit(s) {
+                    Ok(bit) if s.ends_with(expected_rank) => game.en_passant = Some(bit),
+                    _ => return Err(FenError::new(FenField::EnPassant, offset, FenErrorKind::InvalidValue(s.to_string())))
+                }
             }
         };
         
-        let (halfmove_clock, rest) = split_on(rest, ' ');
+        let (offset, halfmove_clock) = next_field(fen, &mut rest, FenField::HalfmoveClock)?;
         match halfmove_clock.parse() {
             Ok(number) => game.halfmove_clock = number,
-            Err(_) => panic!("Invalid halfmove: {}", halfmove_clock),
+            Err(_) => return Err(FenError::new(FenField::HalfmoveClock, offset, FenErrorKind::InvalidValue(halfmove_clock.to_string())))
         }; 
 
-        let (fullmove_number, rest) = split_on(rest, ' ');
+        let (offset, fullmove_number) = next_field(fen, &mut rest, FenField::FullmoveNumber)?;
         match fullmove_number.parse() {
             Ok(number) => game.fullmove_number = number,
-            Err(_) => panic!("Invalid fullmove: {}", fullmove_number),
+            Err(_) => return Err(FenError::new(FenField::FullmoveNumber, offset, FenErrorKind::InvalidValue(fullmove_number.to_string())))
         };
 
-        game
+        if !rest.is_empty() {
+            return Err(FenError::new(FenField::FullmoveNumber, fen.len() - rest.len(), FenErrorKind::TrailingInput(rest.to_string())));
+        }
+
+        game.hash = game.compute_hash();
+        Ok(game)
     }
 
+    // The reverse of `read_FEN`: describes the current position as a FEN string.
+    // Chess960 castling rights are written in X-FEN: K, Q, k and q, unless another rook stands further out on the same
+    // side, in which case the rook's file is given instead.
+    #[allow(non_snake_case)]
+    fn to_FEN(&self) -> String {
+        self.write_FEN(false)
+    }
+
+    // Like `to_FEN`, but castling rights are always written as rook files (Shredder-FEN): HAha for the normal setup.
+    #[allow(non_snake_case)]
+    fn to_shredder_FEN(&self) -> String {
+        self.write_FEN(true)
+    }
+
+    #[allow(non_snake_case)]
+    fn write_FEN(&self, shredder: bool) -> String {
+        // Piece placement, from rank 8 down to rank 1. Runs of empty squares are written as a single digit.
+        let mut rows = Vec::new();
+        for rank in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(rank * 8 + file) {
+                    None => empty += 1,
+                    Some(piece) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(piece.to_char());
+                    }
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+
+        let color_to_move = match self.active_color {
+            Color::White => "w",
+            Color::Black => "b"
+        };
+
+        let castling = self.castling_field(shredder);
+
+        let en_passant = match self.en_passant.map(bit_to_position) {
+            Some(Ok(position)) => position.trim_end().to_string(),
+            _ => "-".to_string()
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            rows.join("/"),
+            color_to_move,
+            castling,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    fn castling_field(&self, shredder: bool) -> String {
+        let mut castling = String::new();
+        for color in [Color::White, Color::Black] {
+            let back_rank = if color == Color::White { 0 } else { 56 };
+            let rook_files = (self.boards[color as usize][PieceType::Rook as usize] >> back_rank) & 0xff;
+
+            for (side, letter) in [(CastlingSide::Kingside, 'k'), (CastlingSide::Queenside, 'q')] {
+                if !self.castling_rights.contains(CastlingRights::of(color, side)) {
+                    continue;
+                }
+
+                let file = self.castling_rooks[color as usize][side as usize] % 8;
+                let outermost = match side {
+                    CastlingSide::Kingside => rook_files >> file <= 1,
+                    CastlingSide::Queenside => rook_files & ((1 << file) - 1) == 0
+                };
+                let letter = if shredder || (self.chess960 && !outermost) { COL_MAP[file] } else { letter };
+                castling.push(if color == Color::White { letter.to_ascii_uppercase() } else { letter });
+            }
+        }
+
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        castling
+    }
+
+    // The file the king of `color` castles from: wherever it stands on its back rank, or the e-file if it isn't there.
+    fn home_king_file(&self, color: Color) -> usize {
+        let back_rank = if color == Color::White { 0 } else { 56 };
+        let kings = (self.boards[color as usize][PieceType::King as usize] >> back_rank) & 0xff;
+        if kings == 0 { 4 } else { kings.trailing_zeros() as usize }
+    }
+
+}
+
+/*
+    Two games are equal when they describe the same position: the same piece on every square, the same side to move,
+    castling rights, en passant square and clocks.
+    We can't just derive this, since `history` is about how we got here, not where we are. The bitboards and the
+    occupancy boards follow from the mailbox, so comparing the mailbox is enough.
+*/
+impl PartialEq for Game {
+    fn eq(&self, other: &Game) -> bool {
+        self.mailbox == other.mailbox
+            && self.active_color == other.active_color
+            && self.castling_rights == other.castling_rights
+            && self.castling_rooks == other.castling_rooks
+            && self.en_passant == other.en_passant
+            && self.halfmove_clock == other.halfmove_clock
+            && self.fullmove_number == other.fullmove_number
+    }
+}
+
+// Implementing Display (instead of a hand rolled `to_string` method) gives us `game.to_string()` for free via the ToString trait,
+// and also lets us use a Game directly inside println!("{}", game).
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+       let mut board = "".to_owned();
+       let mut temp = "".to_owned();
+
+       for (i, square) in self.mailbox.iter().enumerate() {
+        match square {
+            None => temp.push_str(&index_to_position(i)),
+            Some(piece) => temp.push_str(&piece.to_string()),
+        }
+
+        if (i + 1) % 8 == 0 {
+            temp.push('\n');
+            board.insert_str(0, &temp);
+            temp.clear();
+        }
+       }
+       // incase the if block was not activated
+       board.insert_str(0, &temp);
+
+       write!(f, "{}", board)
+    }
 }
 
+    // Places the pieces of one FEN row on the squares `row_start..row_start + 8`.
     fn parse_row(
+        game: &mut Game,
         row: &str,
-        mut piece_index: usize,
-        mut piece_position: usize
-    ) -> (Vec<Piece>, VecDeque<Square>) {
-        let mut pieces = Vec::new();
-        let mut squares = VecDeque::new();
-
+        row_start: usize,
+        offset: usize
+    ) -> Result<(), FenError> {
+        let mut piece_position = row_start;
         let mut color;
+        let rank = row_start / 8 + 1;
 
         // Defining a local macro, which is just a function expanded at compile time itself.
         // It has to be local because we want it to be able to access the local variables
@@ -415,21 +550,24 @@ impl Game {
         macro_rules! add_piece {
             ($piece_type: ident) => {
                 {
-                    let piece = Piece {
-                        color: color,
-                        position: (1 as u64) << piece_position,
-                        piece_type: PieceType::$piece_type
-                    };
-                    let square = Square::Occupied(piece_index);
-                    pieces.push(piece);
-                    squares.push_front(square);
+                    game.put_piece(piece_position, Piece { color, piece_type: PieceType::$piece_type });
                     piece_position += 1;
-                    piece_index += 1;
                 }
             };
         }
 
-        for ch in row.chars() {
+        for (i, ch) in row.char_indices() {
+            // A row describes exactly 8 squares, so there must not be anything left to place once we are past the h-file
+            let squares_needed = match ch {
+                '1'..='8' => ch.to_digit(10).unwrap_or(0) as usize,
+                'r' | 'n' | 'b' | 'q' | 'k' | 'p' | 'R' | 'N' | 'B' | 'Q' | 'K' | 'P' => 1,
+                _ => return Err(FenError::new(FenField::PiecePlacement, offset + i, FenErrorKind::InvalidCharacter(ch)))
+            };
+            if piece_position + squares_needed > row_start + 8 {
+                let squares = piece_position - row_start + squares_needed;
+                return Err(FenError::new(FenField::PiecePlacement, offset + i, FenErrorKind::RowLength { rank, squares }));
+            }
+
             let is_upper = ch.is_ascii_uppercase();
             color = if is_upper {Color::White} else {Color::Black};
             match ch.to_ascii_lowercase() {
@@ -438,20 +576,37 @@ impl Game {
                 'b' => add_piece!(Bishop),
                 'q' => add_piece!(Queen),
                 'k' => add_piece!(King),
+                'p' if rank == 1 || rank == 8 => {
+                    return Err(FenError::new(FenField::PiecePlacement, offset + i, FenErrorKind::PawnOnBackRank(rank)));
+                },
                 'p' => add_piece!(Pawn),
                 num => {
                     match num.to_digit(10) { // 10 -> base 10 numbers (decimal numbers) {
-                        None => panic!("Invalid Input: {}", num),
-                        Some(number) => for i in 0..number {
-                            squares.push_front(Square::Empty);
-                            piece_position += 1;
-                        }
+                        Some(number) if number >= 1 => piece_position += number as usize, // empty squares are simply skipped
+                        _ => return Err(FenError::new(FenField::PiecePlacement, offset + i, FenErrorKind::InvalidCharacter(ch)))
                     }
                 }
             }
         }
 
-        (pieces, squares)
+        if piece_position != row_start + 8 {
+            let squares = piece_position - row_start;
+            return Err(FenError::new(FenField::PiecePlacement, offset + row.len(), FenErrorKind::RowLength { rank, squares }));
+        }
+
+        Ok(())
+    }
+
+    // Pulls the next space separated field off the front of `rest`, along with where it starts inside the full FEN string.
+    fn next_field<'a>(fen: &'a str, rest: &mut &'a str, field: FenField) -> Result<(usize, &'a str), FenError> {
+        let offset = fen.len() - rest.len();
+        let (value, remainder) = split_on(rest, ' ');
+        if value.is_empty() {
+            return Err(FenError::new(field, offset, FenErrorKind::MissingField));
+        }
+
+        *rest = remainder;
+        Ok((offset, value))
     }
 
     // This function will separate 1 string into 2 strings, on the separator character (omitting it)
@@ -459,7 +614,8 @@ impl Game {
     fn split_on(s: &str, sep: char) -> (&str, &str) {
         // .enumerate is used to get both i and item, which is the index as well as the value of elements present in string s.
         // Simply calling for i in s.chars() would have returned the values of the subsequent chars in the string s.
-        for (i, item) in s.chars().enumerate() {
+        // (char_indices rather than chars().enumerate(), because slicing a &str needs a byte offset and not a character count)
+        for (i, item) in s.char_indices() {
             if item == sep {
                 return (&s[0..i], &s[i+1..]);
             }
@@ -469,21 +625,28 @@ impl Game {
     }
 
 impl Piece {
-    fn to_string(&self) -> String {
-        let mut result = match self.piece_type {
-            PieceType::Pawn => "p ",
-            PieceType::Rook => "r ",
-            PieceType::Knight => "n ",
-            PieceType::Bishop => "b ",
-            PieceType::Queen => "q ",
-            PieceType::King => "k "
-        }.to_string();
+    // The letter FEN uses for this piece: uppercase for white, lowercase for black.
+    fn to_char(self) -> char {
+        let letter = match self.piece_type {
+            PieceType::Pawn => 'p',
+            PieceType::Rook => 'r',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k'
+        };
 
         if self.color == Color::White {
-            result.make_ascii_uppercase();
+            letter.to_ascii_uppercase()
+        } else {
+            letter
         }
+    }
+}
 
-        result
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.to_char())
     }
 }
 
@@ -509,10 +672,10 @@ static COL_MAP: [char; 8] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
 // Function to decipher the chess piece position from a given bit string (u64)
 fn bit_to_position(bit_string: PiecePosition) -> Result <String, PiecePositionErrors> {
     if bit_string == 0 {
-        return Err(PiecePositionErrors::EmptyBitString);
+        Err(PiecePositionErrors::EmptyBitString)
     } else {
         let set_index = find_set_bit(bit_string);
-        return Ok(index_to_position(set_index));
+        Ok(index_to_position(set_index))
     }
 }
 
@@ -523,7 +686,7 @@ fn position_to_bit(position: &str) -> Result<PiecePosition, String> {
 
     let bytes = position.as_bytes();
     let byte0 = bytes[0];
-    if byte0 < 97 || byte0 >= (97+8) {
+    if !(97..97+8).contains(&byte0) {
         return Err(format!("Invalid column character: {}, string: {}", byte0 as char, position));
     } 
 
@@ -533,7 +696,7 @@ fn position_to_bit(position: &str) -> Result<PiecePosition, String> {
     let row;
 
     match (byte1 as char).to_digit(10) {
-        Some(number) => if number < 1 || number > 8 {
+        Some(number) => if !(1..=8).contains(&number) {
             return Err(format!("Invalid row character: {}, string: {}", byte1 as char, position));
         } else {
             row = number - 1;
@@ -542,7 +705,7 @@ fn position_to_bit(position: &str) -> Result<PiecePosition, String> {
     }
 
     let square_number = row * 8 + column;
-    let bit = (1 as u64) << square_number;
+    let bit = 1 << square_number;
 
     Ok(bit)
 }
@@ -627,12 +790,19 @@ fn index_to_position(bit_string: usize) -> String {
     format!("{}{} ", COL_MAP[col], row)
 }
 
+// Same as `index_to_position`, minus the trailing space that is only there to line up the board printout.
+// Handy whenever a square has to be written into a move ("e2e4") or a FEN string.
+fn square_name(index: usize) -> String {
+    index_to_position(index).trim_end().to_string()
+}
+
 /*
     Given a bit string, this is how one would usually find the position of the set bit.
     But since, we would have to do a lot of calculations, we wanted something more efficient and went with lookup tables.
     Magic Property: If the input set is a 64 bit string, with only bit set, if you mod the resulting number by 67, all numbers produce a unique number and hence this property can be used to construct a lookup table.
     Check out the function find_set_bit to understand the implementation
 */
+#[allow(dead_code)] // kept around as the readable reference for `find_set_bit`
 fn find_set_bit_inefficient(mut bit_string: u64) -> usize {
     let mut leading_zeros = 0;
     while bit_string & 1 == 0 {
@@ -689,8 +859,151 @@ fn find_set_bit(bit_string: u64) -> usize {
     MOD67TABLE[remainder]
 }
 
+// Removes the lowest set bit from a bitboard and returns its square number.
+// `x & x.wrapping_neg()` isolates the lowest set bit (two's complement trick), which is exactly the single bit input `find_set_bit` wants.
+fn pop_lowest_bit(bit_string: &mut u64) -> usize {
+    let lowest = *bit_string & bit_string.wrapping_neg();
+    *bit_string ^= lowest;
+    find_set_bit(lowest)
+}
+
+// `chessengine perft <depth> [fen]` counts the leaf nodes of the move tree, `chessengine divide <depth> [fen]` also
+// prints the count below every root move. Without a FEN the starting position is used.
+fn run_perft(command: &str, args: &[String]) {
+    let depth: usize = match args.first().and_then(|depth| depth.parse().ok()) {
+        Some(depth) => depth,
+        None => {
+            eprintln!("Usage: chessengine {} <depth> [fen]", command);
+            return;
+        }
+    };
+
+    // The FEN may be passed as one quoted argument or as separate words, joining them handles both
+    let fen = if args.len() > 1 { args[1..].join(" ") } else { STARTING_FEN.to_string() };
+    let mut game = match Game::read_FEN(&fen) {
+        Ok(game) => game,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    let nodes = if command == "divide" {
+        let counts = perft::divide(&mut game, depth);
+        for (m, count) in &counts {
+            println!("{}: {}", m, count);
+        }
+        counts.iter().map(|(_, count)| count).sum()
+    } else {
+        perft::perft(&mut game, depth)
+    };
+    let elapsed = start.elapsed();
+
+    println!();
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {} ms ({:.0} nodes/second)", elapsed.as_millis(), nodes as f64 / elapsed.as_secs_f64().max(1e-9));
+}
+
+// `chessengine bench [depth]` searches a fixed set of positions to a fixed depth (5 by default): with only the hash move
+// first and the captures ordered by MVV-LVA, with the full move ordering, and with the selective search on top of that.
+// It prints the node counts of all three.
+fn run_bench(args: &[String]) {
+    let depth: usize = match args.first().map(|depth| depth.parse()) {
+        None => 5,
+        Some(Ok(depth)) if depth > 0 => depth,
+        Some(_) => {
+            eprintln!("Usage: chessengine bench [depth]");
+            return;
+        }
+    };
+
+    magic::init();
+    let start = Instant::now();
+    let plain = search::bench(depth, false, search::SearchParams::OFF);
+    let ordered = search::bench(depth, true, search::SearchParams::OFF);
+    let selective = search::bench(depth, true, search::SearchParams::default());
+
+    println!("{:>12} {:>12} {:>12}  position", "MVV-LVA", "ordered", "selective");
+    for (((fen, plain), ordered), selective) in search::BENCH_POSITIONS.iter().zip(&plain).zip(&ordered).zip(&selective) {
+        println!("{:>12} {:>12} {:>12}  {}", plain, ordered, selective, fen);
+    }
+    let sum = |nodes: &[u64]| -> u64 { nodes.iter().sum() };
+    let (plain, ordered, selective) = (sum(&plain), sum(&ordered), sum(&selective));
+    println!("{:>12} {:>12} {:>12}  total at depth {}", plain, ordered, selective, depth);
+    println!();
+    println!("Move ordering saves {:.1}% of the nodes", 100.0 * (1.0 - ordered as f64 / plain.max(1) as f64));
+    println!("Selective search saves another {:.1}%", 100.0 * (1.0 - selective as f64 / ordered.max(1) as f64));
+    println!("Time: {} ms", start.elapsed().as_millis());
+}
+
+// `chessengine pgn <file>`: reads every game in a PGN file and writes them back out in tidy form.
+// The games go to stdout (so the output is a PGN file again), a line per game about how it ended goes to stderr.
+fn run_pgn(args: &[String]) {
+    let path = match args.first() {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: chessengine pgn <file>");
+            return;
+        }
+    };
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            eprintln!("Can't read {}: {}", path, error);
+            return;
+        }
+    };
+
+    let games = match pgn::parse_pgn(&text) {
+        Ok(games) => games,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+
+    for (i, game) in games.iter().enumerate() {
+        match (game.to_pgn(), game.final_position()) {
+            (Ok(pgn), Ok(position)) => {
+                if i > 0 {
+                    println!();
+                }
+                print!("{}", pgn);
+                eprintln!(
+                    "Game {}: {} - {}, {} after {} moves, final position {}",
+                    i + 1,
+                    game.tag("White").unwrap_or("?"),
+                    game.tag("Black").unwrap_or("?"),
+                    game.result,
+                    game.moves.len(),
+                    position.to_FEN()
+                );
+            },
+            (Err(error), _) | (_, Err(error)) => eprintln!("Game {}: {}", i + 1, error)
+        }
+    }
+}
+
+// Without any arguments the engine speaks UCI on stdin/stdout, which is what chess GUIs expect when they launch an engine.
+// `chessengine play` is for playing against it yourself, see play.rs.
 fn main() {
-    let position: u64 = 1;
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(|command| command.as_str()) {
+        None | Some("uci") => uci::run(),
+        Some(command @ ("perft" | "divide")) => run_perft(command, &args[2..]),
+        Some("bench") => run_bench(&args[2..]),
+        Some("pgn") => run_pgn(&args[2..]),
+        Some("play") => play::run(&args[2..]),
+        Some("demo") => demo(),
+        Some(other) => eprintln!("Unknown command: {}", other)
+    }
+}
+
+// The playground we used while building the board representation, kept around as `chessengine demo`.
+fn demo() {
+    let _position: u64 = 1;
 
     /*
         println!("{}", position << 63);
@@ -712,17 +1025,392 @@ fn main() {
     */
     let game = Game::initialize();
 
-    let fen_str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let fen_str = STARTING_FEN;
 
     let (first_row, rest) = split_on(fen_str, '/');
 
     println!("{}", first_row);
     println!("{}", rest);
 
-    println!("{}", game.to_string());
+    println!("{}", game);
 
-    let something = Game::read_FEN(fen_str);
-    println!("{}", something.to_string());
+    let something = Game::read_FEN(fen_str).expect("the starting position is a valid FEN");
+    println!("{}", something);
 
     println!("{:#?}, {:#?}, {}", something.active_color, something.en_passant, something.fullmove_number);
+    println!("{}", something.to_FEN());
+
+    let moves = something.legal_moves();
+    let move_list: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+    println!("{} legal moves: {}", moves.len(), move_list.join(" "));
+    let san_list: Vec<String> = moves.iter().map(|&m| something.to_san(m)).collect();
+    println!("or in SAN: {}", san_list.join(" "));
+    for san in ["Nf3", "Nd2", "e5", "Ke2x"] {
+        match something.parse_san(san) {
+            Ok(m) => println!("{} is {}", san, m),
+            Err(error) => println!("{}", error)
+        }
+    }
+    println!("check: {}, checkmate: {}, stalemate: {}", something.is_in_check(), something.is_checkmate(), something.is_stalemate());
+    if let Some(reason) = something.draw_reason() {
+        println!("drawn: {:?} (automatic: {})", reason, reason.is_automatic());
+    }
+
+    let mut something = something;
+    something.make_move(moves[9]);
+    println!("after {}:\n{}", moves[9], something);
+    something.unmake_move();
+    println!("and back again:\n{}", something);
+
+    println!("zobrist key: {:016x}", something.hash);
+
+    if let Some(fischer_random) = Game::chess960(chess960::POSITION_COUNT / 3) {
+        println!("Chess960 start position {}:\n{}", chess960::POSITION_COUNT / 3, fischer_random);
+        println!("{} (Shredder-FEN: {})", fischer_random.to_FEN(), fischer_random.to_shredder_FEN());
+    }
+
+    let limits = search::SearchLimits { depth: Some(4), ..search::SearchLimits::default() };
+    let result = search::search(&mut something, &limits);
+    if let Some(best_move) = result.best_move {
+        println!("after looking {} moves ahead, the engine would play {} (score {})", result.depth, something.to_san(best_move), result.score);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use movegen::{Move, MoveKind};
+
+    // The perft positions plus a few odd ones (en passant squares for both colors, partial castling rights, big clocks)
+    const FEN_CORPUS: [&str; 10] = [
+        STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b Kq e3 0 3",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "8/8/4k3/8/8/4K3/8/8 b - - 87 140",
+        "7k/8/8/8/8/8/8/R6K w - - 0 1"
+    ];
+
+    #[test]
+    fn to_fen_writes_back_what_read_fen_read() {
+        for fen in FEN_CORPUS {
+            assert_eq!(Game::read_FEN(fen).unwrap().to_FEN(), fen);
+        }
+    }
+
+    #[test]
+    fn initialize_matches_the_starting_fen() {
+        assert_eq!(Game::initialize().to_FEN(), STARTING_FEN);
+        assert!(Game::initialize() == Game::read_FEN(STARTING_FEN).unwrap());
+    }
+
+    #[test]
+    fn fen_round_trip_over_played_positions() {
+        // Walk a few plies down from every corpus position, always picking a move in a fixed pattern, and check that
+        // every position we pass through survives a round trip through to_FEN/read_FEN
+        for fen in FEN_CORPUS {
+            let mut game = Game::read_FEN(fen).unwrap();
+            for ply in 0..12 {
+                let again = Game::read_FEN(&game.to_FEN()).unwrap();
+                assert!(again == game, "{} did not round trip", game.to_FEN());
+
+                let moves = game.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                game.make_move(moves[(ply * 7) % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn equality_compares_the_position() {
+        let mut game = Game::read_FEN(STARTING_FEN).unwrap();
+        let moves = game.legal_moves();
+        game.make_move(moves[0]);
+        assert!(game != Game::read_FEN(STARTING_FEN).unwrap());
+        game.unmake_move();
+        assert!(game == Game::read_FEN(STARTING_FEN).unwrap());
+
+        // The same pieces with the other side to move are a different position
+        let game = Game::read_FEN("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(game != Game::read_FEN("4k3/8/8/3P4/8/8/8/4K3 b - - 0 1").unwrap());
+    }
+
+    #[test]
+    fn equality_ignores_how_the_position_was_reached() {
+        // After a capture, and straight from the FEN of the position after it: same position, different history
+        let fen = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1";
+        let mut a = Game::read_FEN(fen).unwrap();
+        let capture = a.legal_moves().into_iter().find(|m| m.to_string() == "e4d5").unwrap();
+        a.make_move(capture);
+        let b = Game::read_FEN("4k3/8/8/3P4/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(a.history.len(), b.history.len());
+        assert!(a == b);
+    }
+
+    /*
+        The board as it was stored before the bitboards and the mailbox: a list of pieces, each with its own one-bit
+        position, and 64 squares that are either empty or point into that list. It only lives on here, so the tests below
+        can check that both representations describe exactly the same boards.
+    */
+    #[derive(Debug, PartialEq, Clone)]
+    enum LegacySquare {
+        Empty,
+        Occupied(usize)
+    }
+
+    #[derive(Clone)]
+    struct LegacyBoard {
+        pieces: Vec<(PiecePosition, Piece)>,
+        squares: Vec<LegacySquare>
+    }
+
+    impl LegacyBoard {
+        fn from_placement(placement: &str) -> LegacyBoard {
+            let mut board = LegacyBoard { pieces: vec![], squares: vec![LegacySquare::Empty; 64] };
+            for (row, text) in placement.split('/').enumerate() {
+                let mut square = (7 - row) * 8;
+                for ch in text.chars() {
+                    match ch.to_digit(10) {
+                        Some(empty) => square += empty as usize,
+                        None => {
+                            let piece_type = match ch.to_ascii_lowercase() {
+                                'p' => PieceType::Pawn,
+                                'r' => PieceType::Rook,
+                                'n' => PieceType::Knight,
+                                'b' => PieceType::Bishop,
+                                'q' => PieceType::Queen,
+                                _ => PieceType::King
+                            };
+                            let color = if ch.is_ascii_uppercase() { Color::White } else { Color::Black };
+                            board.squares[square] = LegacySquare::Occupied(board.pieces.len());
+                            board.pieces.push((1 << square, Piece { color, piece_type }));
+                            square += 1;
+                        }
+                    }
+                }
+            }
+            board
+        }
+
+        fn remove(&mut self, square: usize) {
+            if let LegacySquare::Occupied(idx) = self.squares[square] {
+                self.pieces.remove(idx);
+                self.squares[square] = LegacySquare::Empty;
+                // Everything after the removed piece moved one place up the list
+                for other in self.squares.iter_mut() {
+                    if let LegacySquare::Occupied(i) = other {
+                        if *i > idx {
+                            *i -= 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        fn shift(&mut self, from: usize, to: usize) {
+            if let LegacySquare::Occupied(idx) = self.squares[from] {
+                self.squares[from] = LegacySquare::Empty;
+                self.squares[to] = LegacySquare::Occupied(idx);
+                self.pieces[idx].0 = 1 << to;
+            }
+        }
+
+        // Plays a move on the old board, without looking at the new one. Castling only the normal chess way, which is
+        // all the positions below need.
+        fn play(&mut self, m: &Move) {
+            match m.kind {
+                MoveKind::Capture => self.remove(m.to),
+                // The pawn taken en passant stands next to the one taking it
+                MoveKind::EnPassant => self.remove(m.from / 8 * 8 + m.to % 8),
+                _ => ()
+            }
+
+            let back_rank = m.from / 8 * 8;
+            match m.kind {
+                MoveKind::KingsideCastle => {
+                    self.shift(m.from, back_rank + 6);
+                    self.shift(back_rank + 7, back_rank + 5);
+                },
+                MoveKind::QueensideCastle => {
+                    self.shift(m.from, back_rank + 2);
+                    self.shift(back_rank, back_rank + 3);
+                },
+                _ => self.shift(m.from, m.to)
+            }
+
+            if let (Some(piece_type), LegacySquare::Occupied(idx)) = (m.promotion, self.squares[m.to].clone()) {
+                self.pieces[idx].1.piece_type = piece_type;
+            }
+        }
+
+        fn bitboards(&self) -> Bitboards {
+            let mut boards = [[0; 6]; 2];
+            for (position, piece) in &self.pieces {
+                boards[piece.color as usize][piece.piece_type as usize] |= position;
+            }
+            boards
+        }
+
+        // The old `to_string`: rank 1 is built first and every later rank is put in front of it
+        fn printout(&self) -> String {
+            let mut board = String::new();
+            let mut temp = String::new();
+            for (i, square) in self.squares.iter().enumerate() {
+                match square {
+                    LegacySquare::Empty => temp.push_str(&index_to_position(i)),
+                    LegacySquare::Occupied(idx) => temp.push_str(&self.pieces[*idx].1.to_string())
+                }
+                if (i + 1) % 8 == 0 {
+                    temp.push('\n');
+                    board.insert_str(0, &temp);
+                    temp.clear();
+                }
+            }
+            board
+        }
+    }
+
+    fn assert_same_board(game: &Game, legacy: &LegacyBoard) {
+        let fen = game.to_FEN();
+        for square in 0..64 {
+            let expected = match legacy.squares[square] {
+                LegacySquare::Empty => None,
+                LegacySquare::Occupied(idx) => Some(legacy.pieces[idx].1)
+            };
+            assert_eq!(game.piece_at(square), expected, "{} on {}", fen, square_name(square));
+        }
+
+        let boards = legacy.bitboards();
+        assert_eq!(game.boards, boards, "{}", fen);
+        for color in [Color::White, Color::Black] {
+            let occupancy = boards[color as usize].iter().fold(0, |acc, board| acc | board);
+            assert_eq!(game.occupancy[color as usize], occupancy, "{}", fen);
+        }
+        assert_eq!(game.occupied, game.occupancy[0] | game.occupancy[1], "{}", fen);
+        assert_eq!(game.occupied.count_ones() as usize, legacy.pieces.len(), "{}", fen);
+        assert_eq!(game.to_string(), legacy.printout(), "{}", fen);
+    }
+
+    #[test]
+    fn initialize_agrees_with_the_old_representation() {
+        let placement = STARTING_FEN.split(' ').next().unwrap();
+        assert_same_board(&Game::initialize(), &LegacyBoard::from_placement(placement));
+    }
+
+    #[test]
+    fn read_fen_agrees_with_the_old_representation() {
+        for fen in FEN_CORPUS {
+            let game = Game::read_FEN(fen).unwrap();
+            assert_same_board(&game, &LegacyBoard::from_placement(fen.split(' ').next().unwrap()));
+        }
+    }
+
+    #[test]
+    fn boards_stay_in_sync_while_playing() {
+        // Every make and unmake touches the bitboards, occupancy boards and mailbox separately, so after each one all of
+        // them have to still describe the board the old representation ends up with, playing the same moves on its own
+        for fen in FEN_CORPUS {
+            let mut game = Game::read_FEN(fen).unwrap();
+            let mut legacy = LegacyBoard::from_placement(fen.split(' ').next().unwrap());
+            for ply in 0..16 {
+                let moves = game.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                for &m in &moves {
+                    game.make_move(m);
+                    let mut after = legacy.clone();
+                    after.play(&m);
+                    assert_same_board(&game, &after);
+                    game.unmake_move();
+                    assert_same_board(&game, &legacy);
+                }
+                let m = moves[(ply * 5) % moves.len()];
+                game.make_move(m);
+                legacy.play(&m);
+            }
+        }
+    }
+
+    fn fen_error(fen: &str) -> FenError {
+        Game::read_FEN(fen).unwrap_err()
+    }
+
+    #[test]
+    fn read_fen_reports_broken_piece_placement() {
+        // Digit 9 is not a valid run of empty squares
+        let error = fen_error("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 18, FenErrorKind::InvalidCharacter('9')));
+
+        let error = fen_error("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 13, FenErrorKind::InvalidCharacter('x')));
+
+        // Rank 7 is one square too long, rank 3 one too short
+        let error = fen_error("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 17, FenErrorKind::RowLength { rank: 7, squares: 9 }));
+        let error = fen_error("rnbqkbnr/pppppppp/8/8/8/7/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 25, FenErrorKind::RowLength { rank: 3, squares: 7 }));
+
+        let error = fen_error("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 0, FenErrorKind::RankCount(7)));
+    }
+
+    #[test]
+    fn read_fen_rejects_pawns_on_the_back_ranks() {
+        let error = fen_error("P7/8/8/8/8/8/8/k6K w - - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 0, FenErrorKind::PawnOnBackRank(8)));
+        let error = fen_error("k6K/8/8/8/8/8/8/3p4 b - - 0 1");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 17, FenErrorKind::PawnOnBackRank(1)));
+        assert_eq!(
+            fen_error("k6K/8/8/8/8/8/8/p7 b - - 0 1").to_string(),
+            "Invalid FEN piece placement at character 16: a pawn can't stand on rank 1"
+        );
+
+        // One rank further in they are fine
+        assert!(Game::read_FEN("k6K/P7/8/8/8/8/p7/8 w - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn read_fen_reports_broken_fields() {
+        let error = fen_error("8/8/8/8/8/8/8/8 x - - 0 1");
+        assert_eq!(error, FenError::new(FenField::ActiveColor, 16, FenErrorKind::InvalidValue("x".to_string())));
+
+        let error = fen_error("8/8/8/8/8/8/8/8 w KQz - 0 1");
+        assert_eq!(error, FenError::new(FenField::CastlingRights, 20, FenErrorKind::InvalidCharacter('z')));
+
+        let error = fen_error("8/8/8/8/8/8/8/8 w - e9 0 1");
+        assert_eq!(error.field, FenField::EnPassant);
+        // A target square on the wrong rank for the side to move
+        let error = fen_error("8/8/8/8/8/8/8/8 w - e3 0 1");
+        assert_eq!(error, FenError::new(FenField::EnPassant, 20, FenErrorKind::InvalidValue("e3".to_string())));
+
+        let error = fen_error("8/8/8/8/8/8/8/8 w - - -1 1");
+        assert_eq!(error, FenError::new(FenField::HalfmoveClock, 22, FenErrorKind::InvalidValue("-1".to_string())));
+
+        let error = fen_error("8/8/8/8/8/8/8/8 w - - 0 one");
+        assert_eq!(error.field, FenField::FullmoveNumber);
+
+        let error = fen_error("8/8/8/8/8/8/8/8 w - - 0 1 extra");
+        assert_eq!(error, FenError::new(FenField::FullmoveNumber, 26, FenErrorKind::TrailingInput("extra".to_string())));
+    }
+
+    #[test]
+    fn read_fen_reports_missing_fields() {
+        let error = fen_error("8/8/8/8/8/8/8/8 w - -");
+        assert_eq!(error, FenError::new(FenField::HalfmoveClock, 21, FenErrorKind::MissingField));
+
+        let error = fen_error("");
+        assert_eq!(error, FenError::new(FenField::PiecePlacement, 0, FenErrorKind::MissingField));
+
+        assert_eq!(
+            fen_error("8/8/8/8/8/8/8/8 w").to_string(),
+            "Invalid FEN castling rights at character 17: field is missing"
+        );
+    }
 }
@@ -0,0 +1,458 @@
+/*
+    ///////////////////////////
+    /// Making moves        ///
+    ///////////////////////////
+
+    `make_move` plays a move on the `Game` and `unmake_move` takes it back. Search, perft and replaying a game all walk up
+    and down the move tree thousands of times, so instead of cloning the whole `Game` for every move, we change it in place
+    and push an `Undo` record onto `Game.history` with everything that can't be worked out again from the move itself
+    (the captured piece, the old castling rights, the old en passant square, the old clocks and the old Zobrist key).
+
+    The Zobrist key (`Game.hash`) is updated as we go: every piece that is lifted off or put onto a square XORs its key in,
+    and the castling, en passant and side to move keys are swapped out for the new ones at the end.
+
+    Every change to the board goes through `put_piece` and `take_piece`, which update the piece's bitboard, the occupancy
+    boards, the mailbox and the Zobrist key in one go. That way none of them can ever disagree with the others.
+
+    The search also plays "null moves": passing, just handing the move to the other side (see search.rs). That isn't a
+    real move, so it doesn't go into `Game.history`; `make_null_move` hands back what `unmake_null_move` needs instead.
+*/
+
+use crate::movegen::{en_passant_victim_square, Move, MoveKind};
+use crate::zobrist::{castling_key, piece_key, side_key};
+use crate::{square_name, CastlingRights, CastlingSide, Color, Game, Piece, PiecePosition, PieceType};
+
+#[derive(Debug, Clone)]
+pub struct Undo {
+    mv: Move,
+    captured: Option<Piece>,
+    castling_rights: CastlingRights,
+    en_passant: Option<PiecePosition>,
+    halfmove_clock: usize,
+    fullmove_number: usize,
+    hash: u64
+}
+
+impl Undo {
+    // The Zobrist key of the position the move was played in
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NullMoveUndo {
+    en_passant: Option<PiecePosition>,
+    halfmove_clock: usize,
+    hash: u64
+}
+
+impl Game {
+
+    // Puts a piece on the (empty) square `square`.
+    pub(crate) fn put_piece(&mut self, square: usize, piece: Piece) {
+        let bit = 1 << square;
+        self.boards[piece.color as usize][piece.piece_type as usize] |= bit;
+        self.occupancy[piece.color as usize] |= bit;
+        self.occupied |= bit;
+        self.mailbox[square] = Some(piece);
+        self.hash ^= piece_key(piece.color, piece.piece_type, square);
+    }
+
+    // Takes the piece on `square` off the board and hands it back.
+    fn take_piece(&mut self, square: usize) -> Piece {
+        let piece = match self.mailbox[square].take() {
+            Some(piece) => piece,
+            None => panic!("No piece on {}", square_name(square))
+        };
+
+        let bit = 1 << square;
+        self.boards[piece.color as usize][piece.piece_type as usize] ^= bit;
+        self.occupancy[piece.color as usize] ^= bit;
+        self.occupied ^= bit;
+        self.hash ^= piece_key(piece.color, piece.piece_type, square);
+        piece
+    }
+
+    // Turns the piece on `square` into a `piece_type`, keeping its color. The piece stays where it is, so only its
+    // bitboard, its mailbox entry and its Zobrist key change; the occupancy boards don't.
+    fn set_piece_type(&mut self, square: usize, piece_type: PieceType) {
+        let piece = match self.mailbox[square].as_mut() {
+            Some(piece) => piece,
+            None => panic!("No piece on {}", square_name(square))
+        };
+        let (color, old_type) = (piece.color, piece.piece_type);
+        piece.piece_type = piece_type;
+
+        let bit = 1 << square;
+        self.boards[color as usize][old_type as usize] ^= bit;
+        self.boards[color as usize][piece_type as usize] ^= bit;
+        self.hash ^= piece_key(color, old_type, square) ^ piece_key(color, piece_type, square);
+    }
+
+    // Which castling rights disappear once something moves from (or is captured on) a square: the right that belongs to
+    // the rook on that square, if any. Moving the king loses both rights of its side, `make_move` takes care of that.
+    fn rights_lost_on(&self, square: usize) -> CastlingRights {
+        let mut lost = CastlingRights::NONE;
+        for color in [Color::White, Color::Black] {
+            for side in [CastlingSide::Kingside, CastlingSide::Queenside] {
+                if self.castling_rooks[color as usize][side as usize] == square {
+                    lost |= CastlingRights::of(color, side);
+                }
+            }
+        }
+        lost
+    }
+
+    // Moves whatever stands on `from` to the (empty) square `to`.
+    fn move_piece(&mut self, from: usize, to: usize) {
+        let piece = self.take_piece(from);
+        self.put_piece(to, piece);
+    }
+
+    // Plays a move for `active_color`. The move is expected to come from the move generator; nothing is validated here.
+    pub fn make_move(&mut self, m: Move) {
+        let us = self.active_color;
+        let mut undo = Undo {
+            mv: m,
+            captured: None,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash
+        };
+
+        // Out with the old castling rights and en passant file, the new ones go back in once the move is done
+        self.hash ^= castling_key(self.castling_rights) ^ self.en_passant_key();
+
+        let is_pawn_move = self.boards[us as usize][PieceType::Pawn as usize] & (1 << m.from) != 0;
+        let is_king_move = self.boards[us as usize][PieceType::King as usize] & (1 << m.from) != 0;
+
+        // The capture goes first, so the moving piece can then simply step onto an empty square
+        match m.kind {
+            MoveKind::Capture => undo.captured = Some(self.take_piece(m.to)),
+            MoveKind::EnPassant => undo.captured = Some(self.take_piece(en_passant_victim_square(&m, us))),
+            _ => ()
+        }
+
+        if matches!(m.kind, MoveKind::KingsideCastle | MoveKind::QueensideCastle) {
+            // Lift both pieces first: in Chess960 the king may land where the rook stood, or the other way around
+            let (king_to, rook_from, rook_to) = self.castling_squares(&m);
+            let king = self.take_piece(m.from);
+            let rook = self.take_piece(rook_from);
+            self.put_piece(king_to, king);
+            self.put_piece(rook_to, rook);
+        } else {
+            self.move_piece(m.from, m.to);
+        }
+
+        if let Some(piece_type) = m.promotion {
+            self.set_piece_type(m.to, piece_type);
+        }
+
+        if is_king_move {
+            self.castling_rights.remove(CastlingRights::of(us, CastlingSide::Kingside) | CastlingRights::of(us, CastlingSide::Queenside));
+        }
+        self.castling_rights.remove(self.rights_lost_on(m.from) | self.rights_lost_on(m.to));
+
+        self.en_passant = match m.kind {
+            MoveKind::DoublePawnPush => Some(1 << ((m.from + m.to) / 2)),
+            _ => None
+        };
+
+        if is_pawn_move || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+
+        self.active_color = us.opposite();
+        // The en passant key depends on who is to move, so it can only be worked out now
+        self.hash ^= side_key() ^ castling_key(self.castling_rights) ^ self.en_passant_key();
+        self.history.push(undo);
+    }
+
+    // Passes the move to the other side. The halfmove clock starts again from 0, which keeps the repetition check from
+    // looking back past the null move: a position on the other side of it was reached in an illegal game.
+    pub fn make_null_move(&mut self) -> NullMoveUndo {
+        let undo = NullMoveUndo { en_passant: self.en_passant, halfmove_clock: self.halfmove_clock, hash: self.hash };
+
+        self.hash ^= self.en_passant_key();
+        self.en_passant = None;
+        self.halfmove_clock = 0;
+        self.active_color = self.active_color.opposite();
+        self.hash ^= side_key();
+        undo
+    }
+
+    pub fn unmake_null_move(&mut self, undo: NullMoveUndo) {
+        self.active_color = self.active_color.opposite();
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+    }
+
+    // The move that led to the current position, if we know it.
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().map(|undo| undo.mv)
+    }
+
+    // Takes back the last move played with `make_move`, returning it (or None if there is nothing to take back).
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.history.pop()?;
+        let m = undo.mv;
+
+        self.active_color = self.active_color.opposite();
+        let us = self.active_color;
+
+        if matches!(m.kind, MoveKind::KingsideCastle | MoveKind::QueensideCastle) {
+            let (king_to, rook_from, rook_to) = self.castling_squares(&m);
+            let king = self.take_piece(king_to);
+            let rook = self.take_piece(rook_to);
+            self.put_piece(m.from, king);
+            self.put_piece(rook_from, rook);
+        } else {
+            if m.promotion.is_some() {
+                self.set_piece_type(m.to, PieceType::Pawn);
+            }
+            self.move_piece(m.to, m.from);
+        }
+
+        if let Some(piece) = undo.captured {
+            let square = match m.kind {
+                MoveKind::EnPassant => en_passant_victim_square(&m, us),
+                _ => m.to
+            };
+            self.put_piece(square, piece);
+        }
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        // Moving the pieces back has been XOR-ing keys too, but restoring the key is simpler than undoing all of that
+        self.hash = undo.hash;
+
+        Some(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, coordinates: &str) {
+        let m = game.legal_moves().into_iter().find(|m| m.to_string() == coordinates).unwrap();
+        game.make_move(m);
+    }
+
+    fn assert_same_position(a: &Game, b: &Game) {
+        assert_eq!(a.boards, b.boards);
+        assert_eq!(a.occupancy, b.occupancy);
+        assert_eq!(a.occupied, b.occupied);
+        assert_eq!(a.mailbox, b.mailbox);
+        assert_eq!(a.active_color, b.active_color);
+        assert_eq!(a.castling_rights, b.castling_rights);
+        assert_eq!(a.en_passant, b.en_passant);
+        assert_eq!(a.halfmove_clock, b.halfmove_clock);
+        assert_eq!(a.fullmove_number, b.fullmove_number);
+        assert_eq!(a.hash, b.hash);
+    }
+
+    // Makes and unmakes every legal move two plies deep, checking that the position is restored every single time
+    fn make_unmake_everything(game: &mut Game, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        for m in game.legal_moves() {
+            let before = game.clone();
+            game.make_move(m);
+            make_unmake_everything(game, depth - 1);
+            assert_eq!(game.unmake_move(), Some(m));
+            assert_same_position(game, &before);
+        }
+    }
+
+    #[test]
+    fn unmake_restores_everything() {
+        // Kiwipete: castling both ways, en passant, promotions and plenty of captures
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        make_unmake_everything(&mut game, 2);
+
+        let mut game = Game::read_FEN("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        make_unmake_everything(&mut game, 2);
+
+        assert!(game.unmake_move().is_none());
+    }
+
+    #[test]
+    fn double_push_sets_en_passant_and_clocks() {
+        let mut game = Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3 1").unwrap();
+        play(&mut game, "e2e4");
+        assert_eq!(game.en_passant, Some(1 << 20)); // e3
+        assert_eq!(game.halfmove_clock, 0);
+        assert_eq!(game.fullmove_number, 1);
+        assert_eq!(game.active_color, Color::Black);
+
+        play(&mut game, "g8f6");
+        assert_eq!(game.en_passant, None);
+        assert_eq!(game.halfmove_clock, 1);
+        assert_eq!(game.fullmove_number, 2);
+    }
+
+    #[test]
+    fn castling_moves_the_rook_and_clears_rights() {
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play(&mut game, "e1g1");
+        assert_eq!(game.piece_at(5), Some(Piece { color: Color::White, piece_type: PieceType::Rook }));
+        assert_eq!(game.piece_at(7), None);
+        assert_eq!(game.castling_rights, CastlingRights::BLACKKINGSIDE | CastlingRights::BLACKQUEENSIDE);
+
+        // Black's rook leaving a8 (to take on a1) costs black the queenside right
+        play(&mut game, "a8a1");
+        assert_eq!(game.castling_rights, CastlingRights::BLACKKINGSIDE);
+
+        // Queenside puts the rook on d8 (or d1)
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        play(&mut game, "e8c8");
+        assert_eq!(game.piece_at(59), Some(Piece { color: Color::Black, piece_type: PieceType::Rook }));
+        assert_eq!(game.piece_at(58), Some(Piece { color: Color::Black, piece_type: PieceType::King }));
+        assert_eq!(game.piece_at(56), None);
+        assert_eq!(game.castling_rights, CastlingRights::WHITEKINGSIDE | CastlingRights::WHITEQUEENSIDE);
+        assert_eq!(game.halfmove_clock, 1);
+
+        game.unmake_move();
+        assert_eq!(game.piece_at(56), Some(Piece { color: Color::Black, piece_type: PieceType::Rook }));
+        assert_eq!(game.castling_rights, CastlingRights::ALL);
+    }
+
+    #[test]
+    fn castling_rights_follow_kings_and_rooks() {
+        let fen = "r3k2r/8/8/8/8/8/6b1/R3K2R w KQkq - 0 1";
+
+        // A king step loses both rights of its side, even if the king comes back
+        let mut game = Game::read_FEN(fen).unwrap();
+        play(&mut game, "e1d1");
+        play(&mut game, "e8d8");
+        assert_eq!(game.castling_rights, CastlingRights::NONE);
+
+        // A rook move only loses the right on its own side
+        let mut game = Game::read_FEN(fen).unwrap();
+        play(&mut game, "a1b1");
+        assert_eq!(game.castling_rights, CastlingRights::ALL - CastlingRights::WHITEQUEENSIDE);
+        play(&mut game, "h8h7");
+        assert_eq!(game.castling_rights, CastlingRights::BLACKQUEENSIDE | CastlingRights::WHITEKINGSIDE);
+
+        // A rook captured at home takes its right with it
+        let mut game = Game::read_FEN("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1").unwrap();
+        play(&mut game, "g2h1");
+        assert_eq!(game.castling_rights, CastlingRights::ALL - CastlingRights::WHITEKINGSIDE);
+        assert!(game.legal_moves().iter().all(|m| m.kind != MoveKind::KingsideCastle));
+
+        // Something merely moving onto an empty corner doesn't give rights back (there were none to begin with)
+        let mut game = Game::read_FEN("4k3/8/8/8/8/8/8/4K1R1 w - - 0 1").unwrap();
+        play(&mut game, "g1h1");
+        assert_eq!(game.castling_rights, CastlingRights::NONE);
+    }
+
+    #[test]
+    fn promotion_changes_the_piece_type() {
+        let mut game = Game::read_FEN("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        play(&mut game, "a7b8n");
+        assert_eq!(game.piece_at(57), Some(Piece { color: Color::White, piece_type: PieceType::Knight }));
+        assert_eq!(game.boards[Color::White as usize][PieceType::Pawn as usize], 0);
+        assert_eq!(game.boards[Color::White as usize][PieceType::Knight as usize], 1 << 57);
+        assert_eq!(game.occupied.count_ones(), 3);
+        assert_eq!(game.hash, game.compute_hash());
+
+        game.unmake_move();
+        assert_eq!(game.piece_at(48), Some(Piece { color: Color::White, piece_type: PieceType::Pawn }));
+        assert_eq!(game.occupied.count_ones(), 4);
+    }
+
+    #[test]
+    fn underpromotion_with_capture_comes_back_intact() {
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        for (coordinates, piece_type) in [
+            ("a7b8q", PieceType::Queen),
+            ("a7b8r", PieceType::Rook),
+            ("a7b8b", PieceType::Bishop),
+            ("a7a8n", PieceType::Knight)
+        ] {
+            let mut game = Game::read_FEN(fen).unwrap();
+            let before = game.clone();
+            play(&mut game, coordinates);
+            assert_eq!(game.piece_at(coordinates_square(&coordinates[2..4])), Some(Piece { color: Color::White, piece_type }));
+            assert_eq!(game.halfmove_clock, 0);
+            assert_eq!(game.hash, game.compute_hash());
+
+            game.unmake_move();
+            assert_same_position(&game, &before);
+        }
+    }
+
+    fn coordinates_square(name: &str) -> usize {
+        crate::find_set_bit(crate::position_to_bit(name).unwrap())
+    }
+
+    #[test]
+    fn en_passant_removes_the_passed_pawn() {
+        let mut game = Game::read_FEN("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let before = game.clone();
+        play(&mut game, "e5d6");
+        assert_eq!(game.piece_at(35), None); // d5
+        assert_eq!(game.occupied.count_ones(), 3);
+        assert_eq!(game.en_passant, None);
+
+        game.unmake_move();
+        assert_same_position(&game, &before);
+
+        // The same for black, where the passed pawn stands above the target square
+        let mut game = Game::read_FEN("4k3/8/8/8/5pP1/8/8/4K3 b - g3 0 1").unwrap();
+        play(&mut game, "f4g3");
+        assert_eq!(game.piece_at(30), None); // g4
+        assert_eq!(game.piece_at(22), Some(Piece { color: Color::Black, piece_type: PieceType::Pawn }));
+        assert_eq!(game.boards[Color::White as usize][PieceType::Pawn as usize], 0);
+    }
+
+    #[test]
+    fn single_pushes_leave_no_en_passant_square() {
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        play(&mut game, "e2e3");
+        assert_eq!(game.en_passant, None);
+        play(&mut game, "d7d5");
+        assert_eq!(game.en_passant, Some(1 << 43)); // d6
+        assert_eq!(game.to_FEN(), "rnbqkbnr/ppp1pppp/8/3p4/8/4P3/PPPP1PPP/RNBQKBNR w KQkq d6 0 2");
+    }
+
+    #[test]
+    fn null_move_passes_the_turn() {
+        // Black could take en passant, but not after passing
+        let mut game = Game::read_FEN("4k3/8/8/8/3pP3/8/8/4K2R b K e3 4 1").unwrap();
+        let before = game.clone();
+        let undo = game.make_null_move();
+        assert_eq!(game.active_color, Color::White);
+        assert_eq!(game.en_passant, None);
+        assert_eq!(game.hash, game.compute_hash());
+        assert!(game.history.is_empty());
+
+        // The clock starts over, so the repetition check won't look back past the null move
+        assert_eq!(game.halfmove_clock, 0);
+
+        game.unmake_null_move(undo);
+        assert_same_position(&game, &before);
+
+        // Moves after a null move are taken back as usual
+        let undo = game.make_null_move();
+        play(&mut game, "h1h8");
+        game.unmake_move();
+        game.unmake_null_move(undo);
+        assert_same_position(&game, &before);
+        assert_eq!(game.hash, game.compute_hash());
+    }
+}
@@ -0,0 +1,709 @@
+/*
+    /////////////////////////
+    /// Move generation   ///
+    /////////////////////////
+
+    Everything in here works on the bitboards handed out by `Game::bitboards`, using the same square numbering as the rest of
+    the engine (0 = a1, 7 = h1, 56 = a8, 63 = h8). A bitboard for a set of squares is just those bits OR-ed together.
+
+    The moves produced here are *pseudo-legal*: they follow the movement rules of every piece, but nobody has checked yet
+    whether the side to move leaves its own king in check.
+*/
+
+use std::fmt;
+
+use crate::magic;
+use crate::{
+    find_set_bit, pop_lowest_bit, square_name, Bitboards, CastlingRights, CastlingSide, Color, Game, PiecePosition, PieceType
+};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MoveKind {
+    Quiet,
+    DoublePawnPush,
+    Capture,
+    EnPassant,
+    KingsideCastle,
+    QueensideCastle
+}
+
+// A move only remembers where it goes from and to, plus the bits of extra information that cannot be recovered from the
+// two squares alone (what kind of move it is and, for pawns reaching the last rank, which piece they turn into).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub kind: MoveKind,
+    pub promotion: Option<PieceType>
+}
+
+impl Move {
+    fn new(from: usize, to: usize, kind: MoveKind) -> Move {
+        Move { from, to, kind, promotion: None }
+    }
+}
+
+// Moves are printed in the "long algebraic" coordinate notation: e2e4, e1g1 (castling), e7e8q (promotion).
+// In Chess960 a castling move goes from the king to its own rook instead (e1h1), see `generate_castling_moves`.
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", square_name(self.from), square_name(self.to))?;
+        match self.promotion {
+            Some(PieceType::Queen) => write!(f, "q"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Knight) => write!(f, "n"),
+            _ => Ok(())
+        }
+    }
+}
+
+const RANK_1: PiecePosition = 0xff;
+const RANK_8: PiecePosition = 0xff << 56;
+
+const PROMOTION_PIECES: [PieceType; 4] = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+// Offsets are written as (file step, rank step).
+const KNIGHT_OFFSETS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_OFFSETS: [(i32, i32); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+
+/*
+    Knights and kings always attack the same squares no matter what else is on the board, so we can work out their
+    attacks once for all 64 squares at compile time (const fn) and just look them up during move generation.
+    Going through (file, rank) coordinates instead of shifting the bitboard around means we never have to worry about
+    a knight on the h-file "wrapping around" onto the a-file.
+*/
+const fn leaper_table(offsets: [(i32, i32); 8]) -> [PiecePosition; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let file = (square % 8) as i32;
+        let rank = (square / 8) as i32;
+        let mut i = 0;
+        while i < 8 {
+            let f = file + offsets[i].0;
+            let r = rank + offsets[i].1;
+            if f >= 0 && f < 8 && r >= 0 && r < 8 {
+                table[square] |= 1 << (r * 8 + f);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn pawn_attack_table(rank_step: i32) -> [PiecePosition; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let file = (square % 8) as i32;
+        let rank = (square / 8) as i32 + rank_step;
+        if rank >= 0 && rank < 8 {
+            if file > 0 {
+                table[square] |= 1 << (rank * 8 + file - 1);
+            }
+            if file < 7 {
+                table[square] |= 1 << (rank * 8 + file + 1);
+            }
+        }
+        square += 1;
+    }
+    table
+}
+
+static KNIGHT_ATTACKS: [PiecePosition; 64] = leaper_table(KNIGHT_OFFSETS);
+static KING_ATTACKS: [PiecePosition; 64] = leaper_table(KING_OFFSETS);
+// Indexed by color: white pawns capture towards rank 8, black pawns towards rank 1.
+static PAWN_ATTACKS: [[PiecePosition; 64]; 2] = [pawn_attack_table(1), pawn_attack_table(-1)];
+
+pub fn knight_attacks(square: usize) -> PiecePosition {
+    KNIGHT_ATTACKS[square]
+}
+
+pub fn king_attacks(square: usize) -> PiecePosition {
+    KING_ATTACKS[square]
+}
+
+pub fn pawn_attacks(square: usize, color: Color) -> PiecePosition {
+    PAWN_ATTACKS[color as usize][square]
+}
+
+// Sliding piece attacks come from the magic bitboard tables, see magic.rs.
+pub fn rook_attacks(square: usize, occupied: PiecePosition) -> PiecePosition {
+    magic::rook_attacks(square, occupied)
+}
+
+pub fn bishop_attacks(square: usize, occupied: PiecePosition) -> PiecePosition {
+    magic::bishop_attacks(square, occupied)
+}
+
+pub fn queen_attacks(square: usize, occupied: PiecePosition) -> PiecePosition {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+// All squares holding a piece of the given color.
+pub fn occupancy(boards: &Bitboards, color: Color) -> PiecePosition {
+    boards[color as usize].iter().fold(0, |acc, board| acc | board)
+}
+
+// Turns every target square in `targets` into a move from `from`, marking the ones that land on an enemy piece as captures.
+fn push_moves(moves: &mut Vec<Move>, from: usize, mut targets: PiecePosition, enemy: PiecePosition) {
+    while targets != 0 {
+        let to = pop_lowest_bit(&mut targets);
+        let kind = if enemy & (1 << to) != 0 { MoveKind::Capture } else { MoveKind::Quiet };
+        moves.push(Move::new(from, to, kind));
+    }
+}
+
+// A pawn move onto the last rank is really four moves, one for each piece the pawn can become.
+fn push_pawn_move(moves: &mut Vec<Move>, from: usize, to: usize, kind: MoveKind) {
+    if (RANK_1 | RANK_8) & (1 << to) != 0 {
+        for piece_type in PROMOTION_PIECES {
+            moves.push(Move { from, to, kind, promotion: Some(piece_type) });
+        }
+    } else {
+        moves.push(Move::new(from, to, kind));
+    }
+}
+
+fn generate_pawn_moves(
+    game: &Game,
+    moves: &mut Vec<Move>,
+    pawns: PiecePosition,
+    enemy: PiecePosition,
+    occupied: PiecePosition,
+    captures_only: bool
+) {
+    let us = game.active_color;
+    let (forward, start_rank, last_rank): (i32, usize, PiecePosition) = match us {
+        Color::White => (8, 1, RANK_8),
+        Color::Black => (-8, 6, RANK_1)
+    };
+
+    // A pawn on its last rank can't be there in a real game (the FEN parser turns such positions down), but whoever
+    // builds a board by hand could still put one there. It has nowhere to go, and its push would step off the board.
+    let mut remaining = pawns & !last_rank;
+    while remaining != 0 {
+        let from = pop_lowest_bit(&mut remaining);
+
+        // Pushes: one square forward if it is empty, and two squares from the starting rank if both are empty.
+        // When only captures are wanted, a push still counts if it promotes.
+        let one_step = (from as i32 + forward) as usize;
+        if occupied & (1 << one_step) == 0 && (!captures_only || last_rank & (1 << one_step) != 0) {
+            push_pawn_move(moves, from, one_step, MoveKind::Quiet);
+
+            let two_steps = (one_step as i32 + forward) as usize;
+            if !captures_only && from / 8 == start_rank && occupied & (1 << two_steps) == 0 {
+                moves.push(Move::new(from, two_steps, MoveKind::DoublePawnPush));
+            }
+        }
+
+        // Captures: diagonally forward, onto an enemy piece.
+        let mut captures = pawn_attacks(from, us) & enemy;
+        while captures != 0 {
+            let to = pop_lowest_bit(&mut captures);
+            push_pawn_move(moves, from, to, MoveKind::Capture);
+        }
+
+        // En passant: diagonally forward onto the square the enemy pawn just skipped over.
+        if let Some(target) = game.en_passant {
+            if pawn_attacks(from, us) & target != 0 {
+                moves.push(Move::new(from, find_set_bit(target), MoveKind::EnPassant));
+            }
+        }
+    }
+}
+
+/*
+    Castling here only checks the "board" conditions: the right is still there, king and rook are on their home squares and
+    every square either of them crosses or lands on is empty (apart from the king and rook themselves). Whether the king is
+    in check or walks through an attacked square is left to the legality filter, like every other way of leaving the king
+    in check.
+
+    The same rules cover Chess960, where king and rook start anywhere on the back rank (the king between the rooks). They
+    still end up where they would in normal chess: king on g1 and rook on f1, or king on c1 and rook on d1. Since the king
+    may then not move at all, or land on the rook's square, a Chess960 castling move is written as the king capturing its
+    own rook (e1h1 instead of e1g1), the way UCI does for Chess960.
+*/
+fn generate_castling_moves(game: &Game, moves: &mut Vec<Move>, boards: &Bitboards, occupied: PiecePosition) {
+    let us = game.active_color;
+    let back_rank = match us {
+        Color::White => 0,
+        Color::Black => 56
+    };
+
+    let kings = boards[us as usize][PieceType::King as usize] & (RANK_1 << back_rank);
+    let rooks = boards[us as usize][PieceType::Rook as usize];
+    if kings == 0 {
+        return;
+    }
+    let king_square = find_set_bit(kings & kings.wrapping_neg());
+
+    for (side, kind) in [(CastlingSide::Kingside, MoveKind::KingsideCastle), (CastlingSide::Queenside, MoveKind::QueensideCastle)] {
+        let rook_square = game.castling_rooks[us as usize][side as usize];
+        if !game.castling_rights.contains(CastlingRights::of(us, side))
+            || rooks & (1 << rook_square) == 0
+            || (rook_square > king_square) != (side == CastlingSide::Kingside)
+        {
+            continue;
+        }
+
+        let (king_to, rook_to) = castling_destinations(back_rank, kind);
+        let lowest = king_square.min(rook_square).min(king_to).min(rook_to);
+        let highest = king_square.max(rook_square).max(king_to).max(rook_to);
+        let path = squares_between(lowest, highest) & !(1 << king_square) & !(1 << rook_square);
+        if occupied & path == 0 {
+            let to = if game.chess960 { rook_square } else { king_to };
+            moves.push(Move::new(king_square, to, kind));
+        }
+    }
+}
+
+// Where king and rook end up after castling: (king, rook).
+fn castling_destinations(back_rank: usize, kind: MoveKind) -> (usize, usize) {
+    match kind {
+        MoveKind::KingsideCastle => (back_rank + 6, back_rank + 5),
+        _ => (back_rank + 2, back_rank + 3)
+    }
+}
+
+// Every square from `low` up to `high` (both included), for two squares on the same rank.
+fn squares_between(low: usize, high: usize) -> PiecePosition {
+    ((1 << (high - low + 1)) - 1) << low
+}
+
+// Produces every pseudo-legal move for `game.active_color`.
+pub fn generate_pseudo_legal_moves(game: &Game) -> Vec<Move> {
+    generate_moves(game, false)
+}
+
+// Only the captures (en passant included) and promotions out of `generate_pseudo_legal_moves`, for quiescence search.
+pub fn generate_pseudo_legal_captures(game: &Game) -> Vec<Move> {
+    generate_moves(game, true)
+}
+
+fn generate_moves(game: &Game, captures_only: bool) -> Vec<Move> {
+    let boards = game.bitboards();
+    let us = game.active_color;
+    let own = game.occupancy[us as usize];
+    let enemy = game.occupancy[us.opposite() as usize];
+    let occupied = game.occupied;
+    // Where the pieces (pawns aside) may go
+    let targets = if captures_only { enemy } else { !own };
+
+    let mut moves = Vec::with_capacity(64);
+    let ours = &boards[us as usize];
+
+    generate_pawn_moves(game, &mut moves, ours[PieceType::Pawn as usize], enemy, occupied, captures_only);
+
+    let mut knights = ours[PieceType::Knight as usize];
+    while knights != 0 {
+        let from = pop_lowest_bit(&mut knights);
+        push_moves(&mut moves, from, knight_attacks(from) & targets, enemy);
+    }
+
+    let mut bishops = ours[PieceType::Bishop as usize];
+    while bishops != 0 {
+        let from = pop_lowest_bit(&mut bishops);
+        push_moves(&mut moves, from, bishop_attacks(from, occupied) & targets, enemy);
+    }
+
+    let mut rooks = ours[PieceType::Rook as usize];
+    while rooks != 0 {
+        let from = pop_lowest_bit(&mut rooks);
+        push_moves(&mut moves, from, rook_attacks(from, occupied) & targets, enemy);
+    }
+
+    let mut queens = ours[PieceType::Queen as usize];
+    while queens != 0 {
+        let from = pop_lowest_bit(&mut queens);
+        push_moves(&mut moves, from, queen_attacks(from, occupied) & targets, enemy);
+    }
+
+    let mut kings = ours[PieceType::King as usize];
+    while kings != 0 {
+        let from = pop_lowest_bit(&mut kings);
+        push_moves(&mut moves, from, king_attacks(from) & targets, enemy);
+    }
+
+    if !captures_only {
+        generate_castling_moves(game, &mut moves, boards, occupied);
+    }
+
+    moves
+}
+
+/*
+    ///////////////////////
+    /// Legal moves     ///
+    ///////////////////////
+
+    A pseudo-legal move is illegal exactly when the mover's king is attacked after it has been played. Instead of special
+    casing every way that can happen (moving a pinned piece, stepping into an attacked square, a discovered check along the
+    rank after an en passant capture, ...) we play the move on a scratch copy of the bitboards and ask whether any enemy
+    piece now attacks our king. Pins and discovered checks then fall out naturally: the sliding attacks are recomputed with
+    the new occupancy, so a piece that stepped out of the way no longer blocks anything.
+*/
+
+// Is `square` attacked by any piece of color `by`?
+pub fn is_square_attacked(boards: &Bitboards, occupied: PiecePosition, square: usize, by: Color) -> bool {
+    let theirs = &boards[by as usize];
+    let diagonal_sliders = theirs[PieceType::Bishop as usize] | theirs[PieceType::Queen as usize];
+    let straight_sliders = theirs[PieceType::Rook as usize] | theirs[PieceType::Queen as usize];
+
+    // A pawn of color `by` attacks our square exactly when a pawn of the other color standing on our square would attack it back.
+    pawn_attacks(square, by.opposite()) & theirs[PieceType::Pawn as usize] != 0
+        || knight_attacks(square) & theirs[PieceType::Knight as usize] != 0
+        || king_attacks(square) & theirs[PieceType::King as usize] != 0
+        || bishop_attacks(square, occupied) & diagonal_sliders != 0
+        || rook_attacks(square, occupied) & straight_sliders != 0
+}
+
+// The square of the pawn removed by an en passant capture: one step behind the target square, seen from the capturer.
+pub fn en_passant_victim_square(m: &Move, us: Color) -> usize {
+    match us {
+        Color::White => m.to - 8,
+        Color::Black => m.to + 8
+    }
+}
+
+impl Game {
+
+    // The squares involved in a castling move besides the king's own: (king to, rook from, rook to).
+    pub fn castling_squares(&self, m: &Move) -> (usize, usize, usize) {
+        let back_rank = m.from - m.from % 8;
+        let (color, side) = match (back_rank, m.kind) {
+            (0, MoveKind::KingsideCastle) => (Color::White, CastlingSide::Kingside),
+            (0, _) => (Color::White, CastlingSide::Queenside),
+            (_, MoveKind::KingsideCastle) => (Color::Black, CastlingSide::Kingside),
+            _ => (Color::Black, CastlingSide::Queenside)
+        };
+        let (king_to, rook_to) = castling_destinations(back_rank, m.kind);
+        (king_to, self.castling_rooks[color as usize][side as usize], rook_to)
+    }
+
+    // Plays `m` on the bitboards only (no mailbox, no clocks), which is all the legality check needs.
+    fn apply_to_bitboards(&self, boards: &mut Bitboards, m: &Move) {
+        let us = self.active_color;
+        let from_bit: PiecePosition = 1 << m.from;
+        let to_bit: PiecePosition = 1 << m.to;
+
+        if matches!(m.kind, MoveKind::KingsideCastle | MoveKind::QueensideCastle) {
+            // In Chess960 the king or rook may stay where it is, so no XOR-ing here
+            let (king_to, rook_from, rook_to) = self.castling_squares(m);
+            let ours = &mut boards[us as usize];
+            ours[PieceType::King as usize] = (ours[PieceType::King as usize] & !from_bit) | (1 << king_to);
+            ours[PieceType::Rook as usize] = (ours[PieceType::Rook as usize] & !(1 << rook_from)) | (1 << rook_to);
+            return;
+        }
+
+        let ours = &mut boards[us as usize];
+        let moving = match ours.iter().position(|board| board & from_bit != 0) {
+            Some(piece_type) => piece_type,
+            None => return
+        };
+        ours[moving] ^= from_bit;
+        ours[m.promotion.map_or(moving, |piece_type| piece_type as usize)] |= to_bit;
+
+        let captured_square = match m.kind {
+            MoveKind::EnPassant => en_passant_victim_square(m, us),
+            _ => m.to
+        };
+        for board in boards[us.opposite() as usize].iter_mut() {
+            *board &= !(1 << captured_square);
+        }
+    }
+
+    fn is_legal(&self, boards: &Bitboards, m: &Move) -> bool {
+        let us = self.active_color;
+        let them = us.opposite();
+
+        // Castling has two extra rules on top of "don't end up in check": you can't castle out of check, and the king
+        // can't pass through an attacked square on its way.
+        if matches!(m.kind, MoveKind::KingsideCastle | MoveKind::QueensideCastle) {
+            let (king_to, _, _) = self.castling_squares(m);
+            let mut king_path = squares_between(m.from.min(king_to), m.from.max(king_to));
+            while king_path != 0 {
+                if is_square_attacked(boards, self.occupied, pop_lowest_bit(&mut king_path), them) {
+                    return false;
+                }
+            }
+        }
+
+        let mut after = *boards;
+        self.apply_to_bitboards(&mut after, m);
+        let king = after[us as usize][PieceType::King as usize];
+        if king == 0 {
+            // No king on the board (some test positions do this), so nothing can be left in check.
+            return true;
+        }
+
+        let occupied = occupancy(&after, us) | occupancy(&after, them);
+        !is_square_attacked(&after, occupied, find_set_bit(king), them)
+    }
+
+    // Every move `active_color` can actually play in this position.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let boards = self.bitboards();
+        generate_pseudo_legal_moves(self)
+            .into_iter()
+            .filter(|m| self.is_legal(boards, m))
+            .collect()
+    }
+
+    // The legal captures and promotions, the only moves quiescence search looks at when not in check.
+    pub fn legal_captures(&self) -> Vec<Move> {
+        let boards = self.bitboards();
+        generate_pseudo_legal_captures(self)
+            .into_iter()
+            .filter(|m| self.is_legal(boards, m))
+            .collect()
+    }
+
+    // Whether there is any legal move at all, without sorting out which ones.
+    pub fn has_legal_move(&self) -> bool {
+        let boards = self.bitboards();
+        generate_pseudo_legal_moves(self).iter().any(|m| self.is_legal(boards, m))
+    }
+
+    // Is the king of the side to move currently attacked?
+    pub fn is_in_check(&self) -> bool {
+        let boards = self.bitboards();
+        let us = self.active_color;
+        let king = boards[us as usize][PieceType::King as usize];
+        if king == 0 {
+            return false;
+        }
+
+        is_square_attacked(boards, self.occupied, find_set_bit(king), us.opposite())
+    }
+
+    // Looks up a legal move written in coordinate notation ("e2e4", "e7e8q"), the way UCI sends moves.
+    pub fn find_legal_move(&self, coordinates: &str) -> Option<Move> {
+        self.legal_moves().into_iter().find(|m| m.to_string() == coordinates)
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.is_in_check() && self.legal_moves().is_empty()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.is_in_check() && self.legal_moves().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Piece;
+
+    fn move_strings(fen: &str) -> Vec<String> {
+        let game = Game::read_FEN(fen).unwrap();
+        generate_pseudo_legal_moves(&game).iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn startpos_has_twenty_moves() {
+        let moves = move_strings("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(&"e2e4".to_string()));
+        assert!(moves.contains(&"g1f3".to_string()));
+    }
+
+    #[test]
+    fn sliders_stop_at_blockers() {
+        // Rook on d4 boxed in by its own pawn on d6 and a black pawn on f4
+        let moves = move_strings("4k3/8/3P4/8/3R1p2/8/8/4K3 w - - 0 1");
+        assert!(moves.contains(&"d4d5".to_string()));
+        assert!(!moves.contains(&"d4d6".to_string()));
+        assert!(moves.contains(&"d4f4".to_string()));
+        assert!(!moves.contains(&"d4g4".to_string()));
+        assert!(moves.contains(&"d4d1".to_string()));
+        assert!(moves.contains(&"d4a4".to_string()));
+    }
+
+    #[test]
+    fn knights_do_not_wrap_around_the_board() {
+        let moves = move_strings("4k3/8/8/8/8/8/8/4K2N w - - 0 1");
+        let knight_moves: Vec<&String> = moves.iter().filter(|m| m.starts_with("h1")).collect();
+        assert_eq!(knight_moves.len(), 2);
+    }
+
+    #[test]
+    fn pawns_promote_capture_and_take_en_passant() {
+        let moves = move_strings("1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        for m in ["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7b8q", "a7b8n", "e5d6", "e5e6"] {
+            assert!(moves.contains(&m.to_string()), "missing {}", m);
+        }
+    }
+
+    #[test]
+    fn every_promotion_comes_in_four_flavours() {
+        // Black: a straight push to b1 and a capture on a1, there is nothing to take on c1
+        let moves = move_strings("4k3/8/8/8/8/8/1p6/R3K3 b - - 0 1");
+        let promotions: Vec<&String> = moves.iter().filter(|m| m.starts_with("b2")).collect();
+        assert_eq!(promotions.len(), 8);
+        for m in ["b2b1q", "b2b1r", "b2b1b", "b2b1n", "b2a1q", "b2a1r", "b2a1b", "b2a1n"] {
+            assert!(moves.contains(&m.to_string()), "missing {}", m);
+        }
+        assert!(!moves.contains(&"b2b1".to_string()));
+    }
+
+    #[test]
+    fn en_passant_only_right_after_the_double_push() {
+        let mut game = Game::read_FEN("4k3/2p5/8/3P4/8/8/8/4K3 b - - 0 1").unwrap();
+        let double_push = game.find_legal_move("c7c5").unwrap();
+        assert_eq!(double_push.kind, MoveKind::DoublePawnPush);
+        game.make_move(double_push);
+        assert_eq!(game.find_legal_move("d5c6").map(|m| m.kind), Some(MoveKind::EnPassant));
+
+        // One tempo later the chance is gone
+        game.make_move(game.find_legal_move("e1e2").unwrap());
+        game.make_move(game.find_legal_move("e8e7").unwrap());
+        assert!(game.find_legal_move("d5c6").is_none());
+
+        // Black takes en passant towards its own first rank
+        let moves = legal_move_strings("4k3/8/8/8/5pP1/8/8/4K3 b - g3 0 1");
+        assert!(moves.contains(&"f4g3".to_string()));
+    }
+
+    #[test]
+    fn castling_needs_empty_squares_and_rights() {
+        let moves = move_strings("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1");
+        assert!(moves.contains(&"e1g1".to_string()));
+        assert!(!moves.contains(&"e1c1".to_string()));
+
+        let moves = move_strings("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1");
+        assert!(!moves.contains(&"e1g1".to_string()));
+        assert!(moves.contains(&"e1c1".to_string()));
+    }
+
+    fn legal_move_strings(fen: &str) -> Vec<String> {
+        let game = Game::read_FEN(fen).unwrap();
+        game.legal_moves().iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn pinned_pieces_stay_on_the_pin_line() {
+        // The bishop on d2 is pinned by the rook on d8 and the knight on c2 by the bishop on a4, so neither may move.
+        let moves = legal_move_strings("3r2k1/8/8/8/b7/8/2NB4/3K4 w - - 0 1");
+        assert!(!moves.iter().any(|m| m.starts_with("d2")));
+        assert!(!moves.iter().any(|m| m.starts_with("c2")));
+
+        // A rook pinned along a file can still slide along that file, including capturing the pinner.
+        let moves = legal_move_strings("3r2k1/8/8/8/8/8/3R4/3K4 w - - 0 1");
+        assert!(moves.contains(&"d2d8".to_string()));
+        assert!(moves.contains(&"d2d5".to_string()));
+        assert!(!moves.contains(&"d2e2".to_string()));
+    }
+
+    #[test]
+    fn king_cannot_walk_into_attacks() {
+        // The rook on h2 covers the whole second rank, including the square "behind" the king.
+        let moves = legal_move_strings("4k3/8/8/8/8/8/7r/4K3 w - - 0 1");
+        assert_eq!(moves.len(), 2);
+        assert!(moves.contains(&"e1d1".to_string()));
+        assert!(moves.contains(&"e1f1".to_string()));
+    }
+
+    #[test]
+    fn en_passant_cannot_expose_the_king_along_the_rank() {
+        // Taking d5 en passant would clear both pawns off the fifth rank and leave the king facing the rook on h5.
+        let moves = legal_move_strings("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1");
+        assert!(!moves.contains(&"e5d6".to_string()));
+        assert!(moves.contains(&"e5e6".to_string()));
+    }
+
+    #[test]
+    fn castling_through_check_is_illegal() {
+        // The bishop on a6 covers f1, so white may only castle queenside.
+        let moves = legal_move_strings("4k3/8/b7/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(!moves.contains(&"e1g1".to_string()));
+        assert!(moves.contains(&"e1c1".to_string()));
+
+        // And no castling at all while in check.
+        let moves = legal_move_strings("4k3/8/8/8/8/8/8/R3K2r w Q - 0 1");
+        assert!(!moves.contains(&"e1c1".to_string()));
+    }
+
+    #[test]
+    fn castling_looks_at_the_king_path_only() {
+        // The rook on c8 covers c1, where the king would land
+        let moves = legal_move_strings("2r1k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(!moves.contains(&"e1c1".to_string()));
+        assert!(moves.contains(&"e1g1".to_string()));
+
+        // b1 only has to be empty, the king never crosses it. The rook on a1 being attacked doesn't matter either.
+        let moves = legal_move_strings("1r2k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(moves.contains(&"e1c1".to_string()));
+        let moves = legal_move_strings("r3k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(moves.contains(&"e1c1".to_string()));
+
+        // Black castles the same way, on the eighth rank
+        let moves = legal_move_strings("r3k2r/8/8/8/8/8/8/4K1R1 b kq - 0 1");
+        assert!(!moves.contains(&"e8g8".to_string()));
+        assert!(moves.contains(&"e8c8".to_string()));
+    }
+
+    #[test]
+    fn castling_needs_the_rook_on_its_home_square() {
+        // The FEN still claims both rights, but the h1 rook is gone and a knight stands on a1
+        let moves = legal_move_strings("4k3/8/8/8/8/8/8/N3K3 w KQ - 0 1");
+        assert!(!moves.iter().any(|m| m == "e1g1" || m == "e1c1"));
+    }
+
+    #[test]
+    fn pawns_on_their_last_rank_stay_put() {
+        let mut game = Game::empty();
+        game.put_piece(0, Piece { color: Color::Black, piece_type: PieceType::King });
+        game.put_piece(7, Piece { color: Color::White, piece_type: PieceType::King });
+        // A white pawn on a8 and a black one on d1
+        game.put_piece(56, Piece { color: Color::White, piece_type: PieceType::Pawn });
+        game.put_piece(3, Piece { color: Color::Black, piece_type: PieceType::Pawn });
+        assert!(game.legal_moves().iter().all(|m| m.from == 7));
+
+        game.active_color = Color::Black;
+        assert!(game.legal_moves().iter().all(|m| m.from == 0));
+    }
+
+    #[test]
+    fn check_mate_and_stalemate() {
+        // Fool's mate
+        let game = Game::read_FEN("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert!(game.is_in_check());
+        assert!(game.is_checkmate());
+        assert!(!game.is_stalemate());
+
+        let game = Game::read_FEN("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(!game.is_in_check());
+        assert!(game.is_stalemate());
+        assert!(!game.is_checkmate());
+        assert!(!game.has_legal_move());
+
+        let game = Game::read_FEN("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert!(!game.is_in_check());
+        assert_eq!(game.legal_moves().len(), 20);
+        assert!(game.has_legal_move());
+    }
+
+    #[test]
+    fn captures_are_the_legal_captures_and_promotions() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            // The pinned knight may not take on b3, and the b-pawn can only promote by capturing
+            "nr5k/1P6/8/b7/8/1p6/3N4/4K3 w - - 0 1"
+        ] {
+            let game = Game::read_FEN(fen).unwrap();
+            let expected: Vec<Move> = game
+                .legal_moves()
+                .into_iter()
+                .filter(|m| matches!(m.kind, MoveKind::Capture | MoveKind::EnPassant) || m.promotion.is_some())
+                .collect();
+            assert!(!expected.is_empty(), "{}", fen);
+            assert_eq!(game.legal_captures(), expected, "{}", fen);
+        }
+    }
+}
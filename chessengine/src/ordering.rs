@@ -0,0 +1,247 @@
+/*
+    /////////////////////
+    /// Move ordering ///
+    /////////////////////
+
+    Alpha-beta only cuts off once it has seen a move that is good enough, so the sooner the good moves come, the less of
+    the tree has to be searched. Captures are ordered MVV-LVA: Most Valuable Victim first, and among captures of the same
+    victim the Least Valuable Attacker first. Pawn takes queen comes before rook takes queen, which comes before anything
+    that takes a rook. It is cheap and doesn't look at whether the victim is defended, but the exchanges it puts first
+    are the ones most likely to win material.
+
+    Promotions count as capturing the new piece, so a queen promotion goes about as early as winning a queen.
+
+    The main search (`MoveOrdering`) uses more than that. In a node it tries, in this order:
+
+    1. the hash move: the best move the transposition table remembers for this position;
+    2. good captures, by MVV-LVA: taking something worth at least as much as the attacker, and queen promotions;
+    3. the killer moves: two quiet moves per ply that recently caused a beta cutoff in a sibling position. A move that
+       refutes one line often refutes the line next to it too;
+    4. bad captures (queen takes defended pawn, maybe), still by MVV-LVA;
+    5. the remaining quiet moves, by their history score: a "butterfly" table indexed by [color][from][to] that adds up
+       depth * depth every time that move caused a cutoff anywhere in the tree.
+
+    Killers and history are learned during a search and start empty with every new search. Running `chessengine bench`
+    shows how many nodes they save at a fixed depth.
+*/
+
+use crate::movegen::{Move, MoveKind};
+use crate::search::MAX_PLY;
+use crate::{Game, PieceType};
+
+// Every group of moves scores above the next one
+const HASH_MOVE: i32 = 4_000_000;
+const GOOD_CAPTURE: i32 = 3_000_000;
+const KILLER: i32 = 2_000_000;
+const BAD_CAPTURE: i32 = 1_000_000;
+// History scores are halved once one gets this big, which keeps them below the bad captures
+const HISTORY_LIMIT: i32 = 500_000;
+
+// Only the order matters here, not the actual values (see eval.rs for those). The king is the least welcome attacker.
+fn rank(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6
+    }
+}
+
+// Captures, en passant and promotions: the moves that change the material on the board.
+pub fn is_tactical(m: &Move) -> bool {
+    matches!(m.kind, MoveKind::Capture | MoveKind::EnPassant) || m.promotion.is_some()
+}
+
+// Higher is searched first. Quiet moves all score below every capture of at least a pawn.
+pub fn mvv_lva(game: &Game, m: &Move) -> i32 {
+    let victim = match m.kind {
+        MoveKind::Capture => game.piece_at(m.to).map_or(0, |piece| rank(piece.piece_type)),
+        MoveKind::EnPassant => rank(PieceType::Pawn),
+        _ => 0
+    };
+    let promotion = m.promotion.map_or(0, rank);
+    let attacker = game.piece_at(m.from).map_or(0, |piece| rank(piece.piece_type));
+
+    8 * (victim + promotion) - attacker
+}
+
+// Sorts the moves by MVV-LVA, best first. Moves that score the same keep their order.
+pub fn order_by_mvv_lva(game: &Game, moves: &mut [Move]) {
+    moves.sort_by_key(|m| -mvv_lva(game, m));
+}
+
+// A capture that doesn't risk a piece for something cheaper. Without looking at defenders that is the best guess we have.
+// Underpromotions that don't capture anything are no good captures either: they wait with the bad ones, after the killers.
+fn is_good_capture(game: &Game, m: &Move) -> bool {
+    if m.promotion == Some(PieceType::Queen) {
+        return true;
+    }
+    let victim = match m.kind {
+        MoveKind::Capture => game.piece_at(m.to).map_or(0, |piece| rank(piece.piece_type)),
+        MoveKind::EnPassant => rank(PieceType::Pawn),
+        _ => return false
+    };
+    let attacker = game.piece_at(m.from).map_or(0, |piece| rank(piece.piece_type));
+    victim >= attacker
+}
+
+pub struct MoveOrdering {
+    // Off, the hash move goes first and then only captures are sorted (by MVV-LVA), the way the search used to order its
+    // moves. Handy for comparisons.
+    heuristics: bool,
+    killers: Vec<[Option<Move>; 2]>, // per ply, the most recent one first
+    history: Box<[[[i32; 64]; 64]; 2]>
+}
+
+impl MoveOrdering {
+
+    pub fn new(heuristics: bool) -> MoveOrdering {
+        MoveOrdering {
+            heuristics,
+            killers: vec![[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2])
+        }
+    }
+
+    fn score(&self, game: &Game, m: &Move, hash_move: Option<Move>, ply: usize) -> i32 {
+        if hash_move == Some(*m) {
+            HASH_MOVE
+        } else if !self.heuristics {
+            mvv_lva(game, m)
+        } else if is_tactical(m) {
+            let group = if is_good_capture(game, m) { GOOD_CAPTURE } else { BAD_CAPTURE };
+            group + mvv_lva(game, m)
+        } else if self.killers[ply][0] == Some(*m) {
+            KILLER + 1
+        } else if self.killers[ply][1] == Some(*m) {
+            KILLER
+        } else {
+            self.history[game.active_color as usize][m.from][m.to]
+        }
+    }
+
+    // Sorts the moves of the node at `ply`, the most promising first.
+    pub fn order(&self, game: &Game, moves: &mut [Move], hash_move: Option<Move>, ply: usize) {
+        moves.sort_by_cached_key(|m| -self.score(game, m, hash_move, ply));
+    }
+
+    // `m` caused a beta cutoff at `ply`, with `depth` plies left to search. Only quiet moves are remembered: the
+    // captures are tried early anyway. Call this with `m` already taken back.
+    pub fn record_cutoff(&mut self, game: &Game, m: Move, depth: usize, ply: usize) {
+        if !self.heuristics || is_tactical(&m) {
+            return;
+        }
+
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(m) {
+            killers[1] = killers[0];
+            killers[0] = Some(m);
+        }
+
+        let color = game.active_color as usize;
+        let entry = &mut self.history[color][m.from][m.to];
+        *entry += (depth * depth) as i32;
+        if *entry >= HISTORY_LIMIT {
+            for score in self.history.iter_mut().flatten().flatten() {
+                *score /= 2;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn history_score(&self, color: crate::Color, m: Move) -> i32 {
+        self.history[color as usize][m.from][m.to]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    #[test]
+    fn most_valuable_victim_least_valuable_attacker() {
+        // The black queen on d5 can be taken by a pawn, a knight, a rook or a queen, and the rook on h8 by the queen
+        let game = Game::read_FEN("7r/k7/8/3q3Q/4P3/2N5/8/3RK3 w - - 0 1").unwrap();
+        let mut moves: Vec<Move> = game.legal_moves().into_iter().filter(is_tactical).collect();
+        order_by_mvv_lva(&game, &mut moves);
+        let order: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+        assert_eq!(order, ["e4d5", "c3d5", "d1d5", "h5d5", "h5h8"]);
+        assert!(!is_tactical(&game.find_legal_move("e1e2").unwrap()));
+    }
+
+    #[test]
+    fn promotions_are_tactical() {
+        let game = Game::read_FEN("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let promotion = game.find_legal_move("b7b8q").unwrap();
+        let king_move = game.find_legal_move("e1e2").unwrap();
+        assert!(is_tactical(&promotion));
+        assert!(mvv_lva(&game, &promotion) > mvv_lva(&game, &king_move));
+    }
+
+    #[test]
+    fn ordering_stages() {
+        // White can take the d5 queen four ways (all good), or the h8 rook with the queen (bad, by our guess)
+        let game = Game::read_FEN("7r/k7/8/3q3Q/4P3/2N5/8/3RK3 w - - 0 1").unwrap();
+        let find = |coordinates: &str| game.find_legal_move(coordinates).unwrap();
+        let mut ordering = MoveOrdering::new(true);
+
+        ordering.record_cutoff(&game, find("c3b5"), 3, 2);
+        ordering.record_cutoff(&game, find("e1f2"), 3, 2);
+        // Captures don't become killers
+        ordering.record_cutoff(&game, find("e4d5"), 3, 2);
+        // The same move on another ply only adds to the history
+        ordering.record_cutoff(&game, find("d1d4"), 4, 5);
+        assert_eq!(ordering.history_score(Color::White, find("d1d4")), 16);
+        assert_eq!(ordering.history_score(Color::Black, find("d1d4")), 0);
+
+        let mut moves = game.legal_moves();
+        ordering.order(&game, &mut moves, Some(find("e1e2")), 2);
+        let order: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+        // Hash move, good captures, killers (latest first), bad captures, then history
+        assert_eq!(&order[..9], ["e1e2", "e4d5", "c3d5", "d1d5", "h5d5", "e1f2", "c3b5", "h5h8", "d1d4"]);
+
+        // Without the heuristics only the hash move and the captures move up
+        let mut moves = game.legal_moves();
+        MoveOrdering::new(false).order(&game, &mut moves, Some(find("e1e2")), 2);
+        let order: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+        assert_eq!(&order[..6], ["e1e2", "e4d5", "c3d5", "d1d5", "h5d5", "h5h8"]);
+    }
+
+    #[test]
+    fn underpromotions_come_after_the_killers() {
+        let game = Game::read_FEN("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let find = |coordinates: &str| game.find_legal_move(coordinates).unwrap();
+        assert!(is_good_capture(&game, &find("b7b8q")));
+        assert!(!is_good_capture(&game, &find("b7b8n")));
+
+        let mut ordering = MoveOrdering::new(true);
+        ordering.record_cutoff(&game, find("e1d2"), 3, 0);
+        let mut moves = game.legal_moves();
+        ordering.order(&game, &mut moves, None, 0);
+        let order: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+        assert_eq!(&order[..2], ["b7b8q", "e1d2"]);
+    }
+
+    #[test]
+    fn history_stays_below_the_captures() {
+        let game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let m = game.find_legal_move("g1f3").unwrap();
+        let mut ordering = MoveOrdering::new(true);
+        for _ in 0..10_000 {
+            ordering.record_cutoff(&game, m, 20, 0);
+        }
+        let score = ordering.history_score(Color::White, m);
+        assert!(score > 0 && score < HISTORY_LIMIT, "{}", score);
+    }
+
+    #[test]
+    fn en_passant_takes_a_pawn() {
+        let game = Game::read_FEN("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let m = game.find_legal_move("e5d6").unwrap();
+        assert!(is_tactical(&m));
+        assert_eq!(mvv_lva(&game, &m), 8 - 1);
+    }
+}
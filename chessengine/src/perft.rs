@@ -0,0 +1,118 @@
+/*
+    /////////////
+    /// Perft ///
+    /////////////
+
+    Perft ("performance test") walks the whole legal move tree down to a fixed depth and counts the leaf nodes.
+    The counts for a bunch of well known positions have been worked out by many engines, so if ours match, the move
+    generator (and make/unmake) is almost certainly right. If they don't match, `divide` prints the count below every
+    root move, which lets you compare against another engine and zoom in on the exact move that goes wrong.
+
+    Reference numbers: https://www.chessprogramming.org/Perft_Results
+*/
+
+use crate::movegen::Move;
+use crate::Game;
+
+pub fn perft(game: &mut Game, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = game.legal_moves();
+
+    // "Bulk counting": one ply above the leaves the number of leaves is just the number of legal moves,
+    // so there is no need to actually play each of them.
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for m in moves {
+        game.make_move(m);
+        nodes += perft(game, depth - 1);
+        game.unmake_move();
+    }
+
+    nodes
+}
+
+// Perft split up by root move.
+pub fn divide(game: &mut Game, depth: usize) -> Vec<(Move, u64)> {
+    let mut counts = Vec::new();
+    if depth == 0 {
+        return counts;
+    }
+
+    for m in game.legal_moves() {
+        game.make_move(m);
+        counts.push((m, perft(game, depth - 1)));
+        game.unmake_move();
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    // Lots of en passant and discovered checks along the fifth rank
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    // Promotions, underpromotions and castling rights that get lost by captures on the rook squares
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_4_MIRRORED: &str = "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn check(fen: &str, expected: &[u64]) {
+        let mut game = Game::read_FEN(fen).unwrap();
+        for (i, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&mut game, i + 1), nodes, "depth {} of {}", i + 1, fen);
+        }
+    }
+
+    #[test]
+    fn perft_startpos() {
+        check(STARTPOS, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        check(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        check(POSITION_3, &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        check(POSITION_4, &[6, 264, 9467]);
+        check(POSITION_4_MIRRORED, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        check(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn perft_position_6() {
+        check(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    #[test]
+    fn divide_adds_up_to_perft() {
+        let mut game = Game::read_FEN(KIWIPETE).unwrap();
+        let counts = divide(&mut game, 2);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+
+        let castle = counts.iter().find(|(m, _)| m.to_string() == "e1g1").unwrap();
+        assert_eq!(castle.1, 43);
+    }
+}
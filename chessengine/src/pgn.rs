@@ -0,0 +1,769 @@
+/*
+    ///////////////////////////////////
+    /// Portable Game Notation (PGN) ///
+    ///////////////////////////////////
+
+    PGN is the plain text format practically every chess program uses to store games. A game looks like this:
+
+        [Event "Casual game"]
+        [Site "?"]
+        [Date "2024.03.01"]
+        [Round "?"]
+        [White "Alice"]
+        [Black "Bob"]
+        [Result "1-0"]
+
+        1. e4 e5 2. Nf3 {the most common move} Nc6 (2... d6 $6 3. d4) 3. Bb5 1-0
+
+    - The header is a list of tags. The first seven (the "seven tag roster") are expected in every game, in that order;
+      anything else (`[ECO "C60"]`, `[TimeControl "300+3"]`) may follow. A game that doesn't start from the initial position
+      has a `[FEN "..."]` tag (plus `[SetUp "1"]`).
+    - The movetext lists the moves in SAN (see san.rs) with move numbers, and may contain comments in braces (or after a
+      `;` until the end of the line), numeric annotation glyphs like `$6` ("dubious move", also written as `!`, `?`, `!?`
+      etc.) and variations in parentheses. A variation is an alternative to the move just before it, and can have
+      variations of its own.
+    - Every game ends with its result: `1-0`, `0-1`, `1/2-1/2` or `*` (unknown / still going).
+
+    Every move is replayed through `Game` while reading, so a move that doesn't fit the position is reported right away,
+    and moves are stored both as a `Move` and as SAN written out by us. Writing a game back out then doesn't need to replay
+    anything and always produces the same, tidy form no matter how sloppy the input was.
+
+    Spec: https://www.saremba.de/chessgml/standards/pgn/pgn-complete.htm
+*/
+
+use std::fmt;
+
+use crate::movegen::Move;
+use crate::san::SanError;
+use crate::{Color, FenError, Game, STARTING_FEN};
+
+// The seven tag roster, in the order the tags have to appear in, with the values used when a tag is unknown
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*")
+];
+
+// Exported movetext lines are kept below this length, as the spec asks
+const MAX_LINE_LENGTH: usize = 79;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown
+}
+
+impl GameResult {
+    fn from_pgn(text: &str) -> Option<GameResult> {
+        match text {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*"
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comment_before: Option<String>, // only used for the first move of the game or of a variation
+    pub comment_after: Option<String>,
+    pub variations: Vec<Vec<PgnMove>> // alternatives to this move, each starting from the position before it
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>, // in the order they were read
+    pub moves: Vec<PgnMove>, // the main line
+    pub result: GameResult
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum PgnErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedComment,
+    MalformedTag,
+    UnexpectedToken(String), // e.g. a result inside a variation or a ")" without a "("
+    UnterminatedVariation,
+    VariationWithoutMove, // "(" has to follow the move it is an alternative to
+    InvalidFen(FenError),
+    InvalidMove(SanError)
+}
+
+// What `parse_pgn` hands back when it can't read a game, with the (1 based) line the problem was found on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PgnError {
+    pub line: usize,
+    pub kind: PgnErrorKind
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid PGN at line {}: ", self.line)?;
+        match &self.kind {
+            PgnErrorKind::UnexpectedCharacter(ch) => write!(f, "unexpected character '{}'", ch),
+            PgnErrorKind::UnterminatedComment => write!(f, "comment is never closed"),
+            PgnErrorKind::MalformedTag => write!(f, "malformed tag"),
+            PgnErrorKind::UnexpectedToken(token) => write!(f, "unexpected \"{}\"", token),
+            PgnErrorKind::UnterminatedVariation => write!(f, "variation is never closed"),
+            PgnErrorKind::VariationWithoutMove => write!(f, "variation doesn't follow a move"),
+            PgnErrorKind::InvalidFen(error) => write!(f, "{}", error),
+            PgnErrorKind::InvalidMove(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+/*
+    ///////////////
+    /// Reading ///
+    ///////////////
+*/
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    MoveNumber,
+    San(String),
+    OpenVariation,
+    CloseVariation,
+    Result(GameResult)
+}
+
+// Splits the text up into tokens, each with the line it starts on.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, PgnError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    let error = |line, kind| Err(PgnError { line, kind });
+
+    while i < chars.len() {
+        let ch = chars[i];
+        let start_line = line;
+
+        match ch {
+            '\n' => {
+                line += 1;
+                i += 1;
+            },
+            _ if ch.is_whitespace() => i += 1,
+            // "%" at the very start of a line escapes the whole line, ";" starts a comment that runs until the end of it
+            '%' | ';' if ch == ';' || i == 0 || chars[i - 1] == '\n' => {
+                let end = chars[i..].iter().position(|&ch| ch == '\n').map_or(chars.len(), |end| i + end);
+                if ch == ';' {
+                    let comment: String = chars[i + 1..end].iter().collect();
+                    tokens.push((start_line, Token::Comment(comment.trim().to_string())));
+                }
+                i = end;
+            },
+            '{' => {
+                let end = match chars[i..].iter().position(|&ch| ch == '}') {
+                    Some(end) => i + end,
+                    None => return error(line, PgnErrorKind::UnterminatedComment)
+                };
+                let comment: String = chars[i + 1..end].iter().collect();
+                line += comment.matches('\n').count();
+                // Comments may be wrapped over several lines, inside the game record it is just one line of text
+                let comment = comment.split_whitespace().collect::<Vec<&str>>().join(" ");
+                tokens.push((start_line, Token::Comment(comment)));
+                i = end + 1;
+            },
+            '[' => {
+                let (tag, end) = match read_tag(&chars, i) {
+                    Some(tag) => tag,
+                    None => return error(line, PgnErrorKind::MalformedTag)
+                };
+                tokens.push((start_line, tag));
+                i = end;
+            },
+            '(' => {
+                tokens.push((start_line, Token::OpenVariation));
+                i += 1;
+            },
+            ')' => {
+                tokens.push((start_line, Token::CloseVariation));
+                i += 1;
+            },
+            '*' => {
+                tokens.push((start_line, Token::Result(GameResult::Unknown)));
+                i += 1;
+            },
+            '$' => {
+                let digits: String = chars[i + 1..].iter().take_while(|ch| ch.is_ascii_digit()).collect();
+                match digits.parse() {
+                    Ok(nag) => tokens.push((start_line, Token::Nag(nag))),
+                    Err(_) => return error(line, PgnErrorKind::UnexpectedCharacter('$'))
+                }
+                i += 1 + digits.len();
+            },
+            // Move suffix annotations written apart from their move ("e4 !?") are rare, but legal
+            '!' | '?' => {
+                let annotation: String = chars[i..].iter().take_while(|&&ch| ch == '!' || ch == '?').collect();
+                match annotation_nag(&annotation) {
+                    Some(nag) => tokens.push((start_line, Token::Nag(nag))),
+                    None => return error(line, PgnErrorKind::UnexpectedToken(annotation))
+                }
+                i += annotation.len();
+            },
+            _ if ch.is_ascii_alphanumeric() => {
+                let symbol: String = chars[i..]
+                    .iter()
+                    .take_while(|&&ch| ch.is_ascii_alphanumeric() || "+#=:-/_".contains(ch))
+                    .collect();
+                i += symbol.len();
+
+                if let Some(result) = GameResult::from_pgn(&symbol) {
+                    tokens.push((start_line, Token::Result(result)));
+                } else if symbol.chars().all(|ch| ch.is_ascii_digit()) {
+                    // "12." or "12..." (the dots are optional, in theory)
+                    while i < chars.len() && chars[i] == '.' {
+                        i += 1;
+                    }
+                    tokens.push((start_line, Token::MoveNumber));
+                } else {
+                    tokens.push((start_line, Token::San(symbol)));
+                    let annotation: String = chars[i..].iter().take_while(|&&ch| ch == '!' || ch == '?').collect();
+                    if !annotation.is_empty() {
+                        match annotation_nag(&annotation) {
+                            Some(nag) => tokens.push((start_line, Token::Nag(nag))),
+                            None => return error(line, PgnErrorKind::UnexpectedToken(annotation))
+                        }
+                        i += annotation.len();
+                    }
+                }
+            },
+            // Stray dots, as in "1. ... e5"
+            '.' => i += 1,
+            _ => return error(line, PgnErrorKind::UnexpectedCharacter(ch))
+        }
+    }
+
+    Ok(tokens)
+}
+
+// The NAGs the move suffix annotations stand for
+fn annotation_nag(annotation: &str) -> Option<u8> {
+    match annotation {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+// Reads `[Name "Value"]` starting at the "[", returning the tag and the index just after the "]".
+fn read_tag(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let skip_spaces = |i: &mut usize| {
+        while *i < chars.len() && chars[*i].is_whitespace() && chars[*i] != '\n' {
+            *i += 1;
+        }
+    };
+
+    skip_spaces(&mut i);
+    let name: String = chars[i..].iter().take_while(|ch| ch.is_ascii_alphanumeric() || **ch == '_').collect();
+    if name.is_empty() {
+        return None;
+    }
+    i += name.len();
+
+    skip_spaces(&mut i);
+    if chars.get(i) != Some(&'"') {
+        return None;
+    }
+    i += 1;
+
+    let mut value = String::new();
+    loop {
+        match chars.get(i)? {
+            '"' => break,
+            '\n' => return None,
+            '\\' => {
+                i += 1;
+                value.push(*chars.get(i)?);
+            },
+            &ch => value.push(ch)
+        }
+        i += 1;
+    }
+    i += 1;
+
+    skip_spaces(&mut i);
+    if chars.get(i) != Some(&']') {
+        return None;
+    }
+
+    Some((Token::Tag(name, value), i + 1))
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    // The line of the current token, or of the last one once we've run out
+    fn line(&self) -> usize {
+        self.tokens.get(self.position).or(self.tokens.last()).map_or(1, |(line, _)| *line)
+    }
+
+    fn error<T>(&self, kind: PgnErrorKind) -> Result<T, PgnError> {
+        Err(PgnError { line: self.line(), kind })
+    }
+
+    fn parse_game(&mut self) -> Result<PgnGame, PgnError> {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(name, value)) = self.peek() {
+            tags.push((name.clone(), value.clone()));
+            self.position += 1;
+        }
+
+        let fen = tags.iter().find(|(name, _)| name == "FEN").map_or(STARTING_FEN, |(_, fen)| fen.as_str());
+        let mut game = match Game::read_FEN(fen) {
+            Ok(game) => game,
+            Err(error) => return self.error(PgnErrorKind::InvalidFen(error))
+        };
+
+        let moves = self.parse_line(&mut game, false)?;
+
+        // The result at the end of the movetext wins, a game that just stops falls back on the Result tag
+        let result = match self.peek() {
+            Some(&Token::Result(result)) => {
+                self.position += 1;
+                result
+            },
+            _ => {
+                let tag = tags.iter().find(|(name, _)| name == "Result");
+                tag.and_then(|(_, result)| GameResult::from_pgn(result)).unwrap_or(GameResult::Unknown)
+            }
+        };
+
+        Ok(PgnGame { tags, moves, result })
+    }
+
+    // Reads moves (with their comments, NAGs and variations) until the end of the line: a result, the next game's tags
+    // or the end of the text for the main line, a ")" for a variation. The moves are played on `game` as we go, and
+    // taken back again at the end of a variation.
+    fn parse_line(&mut self, game: &mut Game, in_variation: bool) -> Result<Vec<PgnMove>, PgnError> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut pending_comment: Option<String> = None;
+
+        loop {
+            match self.peek().cloned() {
+                None | Some(Token::Tag(..)) | Some(Token::Result(_)) if !in_variation => break,
+                None => return self.error(PgnErrorKind::UnterminatedVariation),
+                Some(Token::CloseVariation) if in_variation => {
+                    self.position += 1;
+                    break;
+                },
+                Some(Token::MoveNumber) => self.position += 1,
+                Some(Token::Comment(comment)) => {
+                    self.position += 1;
+                    let target = match moves.last_mut() {
+                        Some(last) => &mut last.comment_after,
+                        None => &mut pending_comment
+                    };
+                    *target = Some(match target.take() {
+                        Some(earlier) => format!("{} {}", earlier, comment),
+                        None => comment
+                    });
+                },
+                Some(Token::Nag(nag)) => {
+                    match moves.last_mut() {
+                        Some(last) => last.nags.push(nag),
+                        None => return self.error(PgnErrorKind::UnexpectedToken(format!("${}", nag)))
+                    }
+                    self.position += 1;
+                },
+                Some(Token::San(san)) => {
+                    let mv = match game.parse_san(&san) {
+                        Ok(mv) => mv,
+                        Err(error) => return self.error(PgnErrorKind::InvalidMove(error))
+                    };
+                    moves.push(PgnMove {
+                        mv,
+                        san: game.to_san(mv),
+                        nags: Vec::new(),
+                        comment_before: pending_comment.take(),
+                        comment_after: None,
+                        variations: Vec::new()
+                    });
+                    game.make_move(mv);
+                    self.position += 1;
+                },
+                Some(Token::OpenVariation) => {
+                    let last = match moves.last() {
+                        Some(last) => last.mv,
+                        None => return self.error(PgnErrorKind::VariationWithoutMove)
+                    };
+                    self.position += 1;
+
+                    // The variation replaces the last move, so it starts from the position before it
+                    game.unmake_move();
+                    let variation = self.parse_line(game, true)?;
+                    game.make_move(last);
+
+                    if let Some(last) = moves.last_mut() {
+                        last.variations.push(variation);
+                    }
+                },
+                Some(token) => {
+                    let text = match token {
+                        Token::Result(result) => result.to_string(),
+                        Token::CloseVariation => ")".to_string(),
+                        _ => "[".to_string()
+                    };
+                    return self.error(PgnErrorKind::UnexpectedToken(text));
+                }
+            }
+        }
+
+        if in_variation {
+            for _ in &moves {
+                game.unmake_move();
+            }
+        }
+
+        Ok(moves)
+    }
+}
+
+// Reads every game in a PGN text (an archive usually holds many of them, one after the other).
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+    let mut games = Vec::new();
+
+    while parser.peek().is_some() {
+        games.push(parser.parse_game()?);
+    }
+
+    Ok(games)
+}
+
+/*
+    ///////////////
+    /// Writing ///
+    ///////////////
+*/
+
+// Collects the movetext words and wraps them into lines
+struct MovetextWriter {
+    lines: Vec<String>,
+    current: String,
+    after_parenthesis: bool // "(" sticks to the word after it
+}
+
+impl MovetextWriter {
+    fn push(&mut self, word: &str) {
+        if self.after_parenthesis {
+            self.current.push_str(word);
+            self.after_parenthesis = false;
+            return;
+        }
+        if !self.current.is_empty() && self.current.len() + 1 + word.len() > MAX_LINE_LENGTH {
+            self.lines.push(std::mem::take(&mut self.current));
+        }
+        if !self.current.is_empty() {
+            self.current.push(' ');
+        }
+        self.current.push_str(word);
+    }
+
+    fn open_variation(&mut self) {
+        self.push("(");
+        self.after_parenthesis = true;
+    }
+
+    // ")" sticks to the word before it
+    fn close_variation(&mut self) {
+        self.current.push(')');
+    }
+
+    fn finish(mut self) -> String {
+        if !self.current.is_empty() {
+            self.lines.push(self.current);
+        }
+        self.lines.join("\n")
+    }
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Writes a line of moves starting at `fullmove_number` with `color` to move
+fn write_line(out: &mut MovetextWriter, moves: &[PgnMove], mut color: Color, mut fullmove_number: usize) {
+    // Black moves only get a number ("12...") when they don't directly follow white's move
+    let mut needs_number = true;
+
+    for m in moves {
+        if let Some(comment) = &m.comment_before {
+            out.push(&format!("{{{}}}", comment));
+            needs_number = true;
+        }
+
+        if color == Color::White {
+            out.push(&format!("{}.", fullmove_number));
+        } else if needs_number {
+            out.push(&format!("{}...", fullmove_number));
+        }
+        out.push(&m.san);
+        needs_number = false;
+
+        for nag in &m.nags {
+            out.push(&format!("${}", nag));
+        }
+
+        if let Some(comment) = &m.comment_after {
+            out.push(&format!("{{{}}}", comment));
+            needs_number = true;
+        }
+
+        for variation in &m.variations {
+            out.open_variation();
+            write_line(out, variation, color, fullmove_number);
+            out.close_variation();
+            needs_number = true;
+        }
+
+        if color == Color::Black {
+            fullmove_number += 1;
+        }
+        color = color.opposite();
+    }
+}
+
+impl PgnGame {
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn starting_position(&self) -> Result<Game, FenError> {
+        Game::read_FEN(self.tag("FEN").unwrap_or(STARTING_FEN))
+    }
+
+    // The position at the end of the main line
+    pub fn final_position(&self) -> Result<Game, FenError> {
+        let mut game = self.starting_position()?;
+        for m in &self.moves {
+            game.make_move(m.mv);
+        }
+        Ok(game)
+    }
+
+    pub fn to_pgn(&self) -> Result<String, FenError> {
+        let mut pgn = String::new();
+
+        // The seven tag roster always comes first (the Result tag always agrees with the actual result)...
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.to_string(),
+                _ => self.tag(name).unwrap_or(default).to_string()
+            };
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(&value)));
+        }
+        // ...followed by everything else, in the order we got it
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(roster, _)| roster == name) {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+            }
+        }
+        pgn.push('\n');
+
+        let start = self.starting_position()?;
+        let mut movetext = MovetextWriter { lines: Vec::new(), current: String::new(), after_parenthesis: false };
+        write_line(&mut movetext, &self.moves, start.active_color, start.fullmove_number);
+        movetext.push(&self.result.to_string());
+
+        pgn.push_str(&movetext.finish());
+        pgn.push('\n');
+        Ok(pgn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGALS_MATE: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1750.??.??"]
+[Round "?"]
+[White "Kermur Sire de Legall"]
+[Black "Saint Brie"]
+[Result "1-0"]
+[ECO "C41"]
+
+1. e4 e5 2. Nf3 d6 3. Bc4 Bg4 {Pinning the knight, or so it seems.} 4. Nc3 g6
+5. Nxe5 Bxd1 6. Bxf7+ Ke7 7. Nd5# 1-0
+"#;
+
+    #[test]
+    fn reads_a_game() {
+        let games = parse_pgn(LEGALS_MATE).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+
+        assert_eq!(game.tag("White"), Some("Kermur Sire de Legall"));
+        assert_eq!(game.tag("ECO"), Some("C41"));
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(game.moves.len(), 13);
+        assert_eq!(game.moves[5].comment_after.as_deref(), Some("Pinning the knight, or so it seems."));
+        assert_eq!(game.moves[12].san, "Nd5#");
+        assert!(game.final_position().unwrap().is_checkmate());
+    }
+
+    #[test]
+    fn writes_a_game_back_out() {
+        let games = parse_pgn(LEGALS_MATE).unwrap();
+        assert_eq!(games[0].to_pgn().unwrap(), LEGALS_MATE);
+    }
+
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+[ECO "C41"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 {This is a weak move already.} 4. dxe5 Bxf3 5.
+Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3 c6 9. Bg5 b5 10. Nxb5 cxb5 11. Bxb5+
+Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ Nxb8 17.
+Rd8# 1-0
+"#;
+
+    #[test]
+    fn castling_in_a_game() {
+        let games = parse_pgn(OPERA_GAME).unwrap();
+        let game = &games[0];
+        assert_eq!(game.moves[22].san, "O-O-O");
+        assert!(game.final_position().unwrap().is_checkmate());
+        assert_eq!(game.to_pgn().unwrap(), OPERA_GAME);
+    }
+
+    #[test]
+    fn comments_nags_and_variations() {
+        let text = "{Start} 1. e4 ! e5 (1... c5!? {Sicilian} 2. Nf3 (2. c3) 2... d6) (1... e6) \
+                    2. Nf3 $1 ; end of line comment\n Nc6?? *";
+        let games = parse_pgn(text).unwrap();
+        let game = &games[0];
+
+        assert_eq!(game.result, GameResult::Unknown);
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.moves[0].comment_before.as_deref(), Some("Start"));
+        assert_eq!(game.moves[0].nags, vec![1]);
+
+        let variations = &game.moves[1].variations;
+        assert_eq!(variations.len(), 2);
+        assert_eq!(variations[0][0].san, "c5");
+        assert_eq!(variations[0][0].nags, vec![5]);
+        assert_eq!(variations[0][0].comment_after.as_deref(), Some("Sicilian"));
+        assert_eq!(variations[0][1].variations[0][0].san, "c3");
+        assert_eq!(variations[0][2].san, "d6");
+        assert_eq!(variations[1][0].san, "e6");
+
+        assert_eq!(game.moves[2].nags, vec![1]);
+        assert_eq!(game.moves[2].comment_after.as_deref(), Some("end of line comment"));
+        assert_eq!(game.moves[3].nags, vec![4]);
+
+        let expected = "{Start} 1. e4 $1 e5 (1... c5 $5 {Sicilian} 2. Nf3 (2. c3) 2... d6) (1... e6) 2.\n\
+                        Nf3 $1 {end of line comment} 2... Nc6 $4 *";
+        let pgn = game.to_pgn().unwrap();
+        assert!(pgn.ends_with(&format!("\n\n{}\n", expected)), "{}", pgn);
+
+        // Reading that back gives the same moves, and writing it again the very same text
+        let reread = &parse_pgn(&pgn).unwrap()[0];
+        assert_eq!(reread.moves, game.moves);
+        assert_eq!(reread.to_pgn().unwrap(), pgn);
+    }
+
+    #[test]
+    fn games_from_a_position_and_archives() {
+        let text = r#"[Event "Endgame study"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"]
+
+40... Kd7 41. e4 Kc6 1/2-1/2
+
+[Event "Second game"]
+[Result "0-1"]
+
+1. f3 e5 2. g4 Qh4#
+"#;
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].result, GameResult::Draw);
+        assert_eq!(games[0].final_position().unwrap().to_FEN(), "8/8/2k5/8/4P3/8/8/4K3 w - - 1 42");
+        let pgn = games[0].to_pgn().unwrap();
+        assert!(pgn.starts_with("[Event \"Endgame study\"]\n[Site \"?\"]\n[Date \"????.??.??\"]"));
+        assert!(pgn.contains("[Result \"1/2-1/2\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n"));
+        assert!(pgn.ends_with("\n\n40... Kd7 41. e4 Kc6 1/2-1/2\n"));
+
+        // No result at the end of the movetext: the Result tag has it
+        assert_eq!(games[1].result, GameResult::BlackWins);
+        assert!(games[1].final_position().unwrap().is_checkmate());
+    }
+
+    #[test]
+    fn tag_values_are_escaped() {
+        let games = parse_pgn(r#"[White "The \"Dragon\" \\ fan"] *"#).unwrap();
+        assert_eq!(games[0].tag("White"), Some(r#"The "Dragon" \ fan"#));
+        assert!(games[0].to_pgn().unwrap().contains(r#"[White "The \"Dragon\" \\ fan"]"#));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| parse_pgn(text).unwrap_err();
+
+        let illegal = error("[Event \"?\"]\n\n1. e4 e5\n2. Ke3 *");
+        assert_eq!(illegal.line, 4);
+        assert!(matches!(illegal.kind, PgnErrorKind::InvalidMove(_)));
+        assert_eq!(illegal.to_string(), "Invalid PGN at line 4: Illegal move \"Ke3\"");
+
+        assert_eq!(error("1. e4 {never closed").kind, PgnErrorKind::UnterminatedComment);
+        assert_eq!(error("1. e4 (1. d4 *").kind, PgnErrorKind::UnexpectedToken("*".to_string()));
+        assert_eq!(error("1. e4 (1. d4").kind, PgnErrorKind::UnterminatedVariation);
+        assert_eq!(error("(1. d4) 1. e4 *").kind, PgnErrorKind::VariationWithoutMove);
+        assert_eq!(error("1. e4 ) *").kind, PgnErrorKind::UnexpectedToken(")".to_string()));
+        assert_eq!(error("[Event unquoted]").kind, PgnErrorKind::MalformedTag);
+        assert_eq!(error("1. e4 & *").kind, PgnErrorKind::UnexpectedCharacter('&'));
+        assert!(matches!(error("[FEN \"8/8 w\"] *").kind, PgnErrorKind::InvalidFen(_)));
+    }
+}
@@ -0,0 +1,394 @@
+/*
+    ////////////////////////////
+    /// Playing in the shell ///
+    ////////////////////////////
+
+    `chessengine play [white|black] [depth N | movetime MS] [unicode] [color]` sets up a game between you and the engine
+    in the terminal. `unicode` draws the pieces as chess symbols and `color` paints the squares (see render.rs).
+    You move by typing the move in coordinate notation (e2e4, e7e8q) or SAN (e4, Nf3, O-O), and the engine answers right
+    away. Besides moves you can type:
+
+        undo     take back your last move (and the engine's answer to it)
+        flip     look at the board from the other side
+        fen      print the current position as a FEN string
+        hint     ask the engine what it would play for you
+        resign   give up
+        quit     leave without finishing the game
+
+    Like the UCI loop, a `Session` reads one line at a time and writes everything to a `Write`, so the tests can drive it
+    without a terminal.
+*/
+
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+use crate::draw::DrawReason;
+use crate::movegen::Move;
+use crate::render::{Glyphs, RenderOptions};
+use crate::san::SanErrorKind;
+use crate::search::{self, SearchLimits};
+use crate::timeman::TimeBudget;
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB};
+use crate::{magic, Color, Game, STARTING_FEN};
+
+const DEFAULT_MOVETIME: u64 = 1000;
+
+const HELP: &str = "Type your moves as e2e4 or Nf3. Other commands: undo, flip, fen, hint, resign, quit.";
+
+// How hard the engine thinks about every move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strength {
+    Depth(usize),
+    Movetime(u64) // milliseconds
+}
+
+pub struct Session<W: Write> {
+    game: Game,
+    out: W,
+    human: Color,
+    board: RenderOptions,
+    strength: Strength,
+    tt: TranspositionTable,
+    over: bool
+}
+
+impl<W: Write> Session<W> {
+
+    pub fn new(game: Game, human: Color, strength: Strength, out: W) -> Session<W> {
+        Session {
+            game,
+            out,
+            human,
+            // Sitting behind the black pieces, you want to see them at the bottom
+            board: RenderOptions { flipped: human == Color::Black, ..RenderOptions::default() },
+            strength,
+            tt: TranspositionTable::new(DEFAULT_HASH_MB),
+            over: false
+        }
+    }
+
+    fn say(&mut self, text: &str) {
+        // There is nobody to tell if the terminal went away
+        let _ = writeln!(self.out, "{}", text);
+    }
+
+    // Greets the player, and lets the engine open the game if it plays white.
+    pub fn start(&mut self) {
+        self.say(HELP);
+        self.engine_move();
+        self.show_board();
+        self.prompt();
+    }
+
+    // Handles one line typed by the player. Returns false once the session is over.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let input = line.trim();
+        match input {
+            "" => (),
+            "quit" | "exit" => return false,
+            "help" => self.say(HELP),
+            "fen" => {
+                let fen = self.game.to_FEN();
+                self.say(&fen);
+            },
+            "flip" => {
+                self.board.flipped = !self.board.flipped;
+                self.show_board();
+            },
+            "undo" => self.undo(),
+            "hint" => self.hint(),
+            "resign" => {
+                let winner = color_name(self.human.opposite());
+                self.say(&format!("You resign. {} wins.", capitalized(winner)));
+                return false;
+            },
+            _ if self.over => self.say("The game is over. You can still undo, or quit."),
+            _ => self.human_move(input)
+        }
+
+        self.prompt();
+        true
+    }
+
+    fn prompt(&mut self) {
+        if !self.over {
+            let color = color_name(self.game.active_color);
+            let _ = write!(self.out, "Your move ({}): ", color);
+            let _ = self.out.flush();
+        }
+    }
+
+    fn show_board(&mut self) {
+        let board = self.game.render(&self.board);
+        let _ = write!(self.out, "{}", board);
+    }
+
+    fn human_move(&mut self, input: &str) {
+        let m = match self.game.find_legal_move(input) {
+            Some(m) => m,
+            None => match self.game.parse_san(input) {
+                Ok(m) => m,
+                Err(error) => {
+                    match error.kind {
+                        SanErrorKind::Malformed => self.say(&format!("\"{}\" is neither a move nor a command. {}", input, HELP)),
+                        _ => self.say(&error.to_string())
+                    }
+                    return;
+                }
+            }
+        };
+
+        self.game.make_move(m);
+        if !self.check_game_over() {
+            self.engine_move();
+            self.check_game_over();
+        }
+        self.show_board();
+    }
+
+    fn think(&mut self) -> Option<Move> {
+        let limits = match self.strength {
+            Strength::Depth(depth) => SearchLimits { depth: Some(depth), ..SearchLimits::default() },
+            Strength::Movetime(ms) => SearchLimits {
+                budget: Some(TimeBudget::fixed(Duration::from_millis(ms))),
+                ..SearchLimits::default()
+            }
+        };
+        search::search_with_progress(&mut self.game, &limits, &mut self.tt, &mut |_| ()).best_move
+    }
+
+    // Lets the engine play, if it is its turn.
+    fn engine_move(&mut self) {
+        if self.over || self.game.active_color == self.human {
+            return;
+        }
+
+        if let Some(m) = self.think() {
+            let san = self.game.to_san(m);
+            self.game.make_move(m);
+            self.say(&format!("Engine plays {}", san));
+        }
+    }
+
+    fn hint(&mut self) {
+        if self.over {
+            self.say("The game is over.");
+            return;
+        }
+
+        match self.think() {
+            Some(m) => {
+                let san = self.game.to_san(m);
+                self.say(&format!("Hint: {}", san));
+            },
+            None => self.say("There is no move to play.")
+        }
+    }
+
+    // Takes back the player's last move, together with the engine's reply if there was one.
+    fn undo(&mut self) {
+        let plies = if self.game.active_color == self.human { 2 } else { 1 };
+        if self.game.history.len() < plies {
+            self.say("Nothing to undo.");
+            return;
+        }
+
+        for _ in 0..plies {
+            self.game.unmake_move();
+        }
+        self.over = false;
+        self.show_board();
+    }
+
+    // Announces the end of the game, if it has ended. Threefold repetition and the fifty move rule end it as well:
+    // the engine always claims the draw, and so do you.
+    fn check_game_over(&mut self) -> bool {
+        let result = if self.game.is_checkmate() {
+            let winner = color_name(self.game.active_color.opposite());
+            Some(format!("Checkmate. {} wins.", capitalized(winner)))
+        } else {
+            self.game.draw_reason().map(|reason| format!("Draw by {}.", draw_description(reason)))
+        };
+
+        match result {
+            Some(result) => {
+                self.say(&result);
+                self.over = true;
+                true
+            },
+            None => false
+        }
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black"
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new()
+    }
+}
+
+fn draw_description(reason: DrawReason) -> &'static str {
+    match reason {
+        DrawReason::Stalemate => "stalemate",
+        DrawReason::InsufficientMaterial => "insufficient material",
+        DrawReason::FivefoldRepetition => "fivefold repetition",
+        DrawReason::SeventyFiveMoveRule => "the seventy-five move rule",
+        DrawReason::ThreefoldRepetition => "threefold repetition",
+        DrawReason::FiftyMoveRule => "the fifty move rule"
+    }
+}
+
+// `chessengine play [white|black] [depth N | movetime MS] [unicode] [color]`
+pub fn run(args: &[String]) {
+    let mut human = Color::White;
+    let mut strength = Strength::Movetime(DEFAULT_MOVETIME);
+    let mut board = RenderOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|value| value.parse::<u64>().ok()).filter(|&value| value > 0);
+        match arg.as_str() {
+            "white" => human = Color::White,
+            "black" => human = Color::Black,
+            "unicode" => board.glyphs = Glyphs::Unicode,
+            "color" => board.colors = true,
+            "depth" => match number() {
+                Some(depth) => strength = Strength::Depth(depth as usize),
+                None => return eprintln!("depth needs a positive number")
+            },
+            "movetime" => match number() {
+                Some(ms) => strength = Strength::Movetime(ms),
+                None => return eprintln!("movetime needs a positive number of milliseconds")
+            },
+            other => return eprintln!("Unknown option {}. Usage: chessengine play [white|black] [depth N | movetime MS] [unicode] [color]", other)
+        }
+    }
+
+    magic::init();
+    let game = Game::read_FEN(STARTING_FEN).expect("the starting position is a valid FEN");
+    let mut session = Session::new(game, human, strength, io::stdout());
+    session.board.glyphs = board.glyphs;
+    session.board.colors = board.colors;
+    session.start();
+
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) if session.handle(&line) => (),
+            _ => break
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(human: Color) -> Session<Vec<u8>> {
+        let game = Game::read_FEN(STARTING_FEN).unwrap();
+        Session::new(game, human, Strength::Depth(1), Vec::new())
+    }
+
+    // Everything written since the last call
+    fn output(session: &mut Session<Vec<u8>>) -> String {
+        String::from_utf8(std::mem::take(&mut session.out)).unwrap()
+    }
+
+    #[test]
+    fn moves_in_either_notation() {
+        let mut session = session(Color::White);
+        session.start();
+        assert!(output(&mut session).ends_with("Your move (white): "));
+
+        assert!(session.handle("e2e4"));
+        let text = output(&mut session);
+        assert!(text.contains("Engine plays "), "{}", text);
+        assert_eq!(session.game.history.len(), 2);
+
+        assert!(session.handle("Nf3"));
+        assert_eq!(session.game.history.len(), 4);
+
+        // The board is shown after every move, white at the bottom
+        let text = output(&mut session);
+        assert!(text.ends_with("1  R  N  B  Q  K  B  .  R\n   a  b  c  d  e  f  g  h\nYour move (white): "), "{}", text);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut session = session(Color::White);
+        session.handle("e2e5");
+        assert!(output(&mut session).contains("neither a move nor a command"));
+        session.handle("Nf4");
+        assert!(output(&mut session).contains("Illegal move"));
+        assert!(session.game.history.is_empty());
+    }
+
+    #[test]
+    fn engine_opens_when_playing_white() {
+        let mut session = session(Color::Black);
+        session.start();
+        let text = output(&mut session);
+        assert!(text.contains("Engine plays "));
+        assert_eq!(session.game.active_color, Color::Black);
+        // Seen from black, rank 8 is at the bottom and the h-file on the left
+        assert!(text.ends_with("8  r  n  b  k  q  b  n  r\n   h  g  f  e  d  c  b  a\nYour move (black): "), "{}", text);
+
+        // The engine's opening move can't be taken back on its own
+        session.handle("undo");
+        assert!(output(&mut session).contains("Nothing to undo."));
+    }
+
+    #[test]
+    fn undo_flip_and_fen() {
+        let mut session = session(Color::White);
+        session.handle("d4");
+        session.handle("undo");
+        assert!(session.game.history.is_empty());
+
+        session.handle("fen");
+        assert!(output(&mut session).contains(STARTING_FEN));
+
+        session.handle("flip");
+        let text = output(&mut session);
+        assert!(text.starts_with("1  R  N  B  K  Q  B  N  R\n2  P  P  P  P  P  P  P  P\n3  .  .  .  .  .  .  .  .\n"), "{}", text);
+    }
+
+    #[test]
+    fn hint_resign_and_quit() {
+        let mut session = session(Color::White);
+        session.handle("hint");
+        assert!(output(&mut session).starts_with("Hint: "));
+        assert!(session.game.history.is_empty());
+
+        assert!(!session.handle("resign"));
+        assert!(output(&mut session).contains("You resign. Black wins."));
+
+        assert!(!session.handle("quit"));
+    }
+
+    #[test]
+    fn announces_the_end_of_the_game() {
+        // Back rank mate in one for white
+        let game = Game::read_FEN("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut session = Session::new(game, Color::White, Strength::Depth(1), Vec::new());
+        session.handle("Ra8#");
+        assert!(output(&mut session).contains("Checkmate. White wins."));
+        session.handle("hint");
+        assert!(output(&mut session).contains("The game is over."));
+        session.handle("Kg2");
+        assert!(output(&mut session).contains("The game is over."));
+
+        // Taking the mate back lets the game go on
+        session.handle("undo");
+        session.handle("Kg2");
+        assert!(output(&mut session).contains("Engine plays "));
+    }
+}
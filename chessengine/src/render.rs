@@ -0,0 +1,237 @@
+/*
+    //////////////////////
+    /// Board printing ///
+    //////////////////////
+
+    `Game`'s Display impl is the bare printout we started out with: coordinates for empty squares, letters for pieces.
+    `Game::render` draws a board meant for people instead, and can be set up with `RenderOptions`:
+
+    - `glyphs`: letters (K, q, ...) or the Unicode chess symbols (♔, ♛, ...). Empty squares are a dot either way.
+    - `labels`: rank numbers on the left, file letters underneath.
+    - `flipped`: black's point of view, rank 1 at the top and the h-file on the left.
+    - `colors`: ANSI background colors for light and dark squares. Only for terminals; leave it off for files and tests.
+    - `highlights`: mark the squares of the last move and the king standing in check. With colors these get their own
+      background, without colors the square is put in brackets: (e2) for the last move, [K] for the checked king.
+
+    Every square takes up exactly three characters, so the output lines up the same way whatever is switched on. Without
+    colors the output is plain text without trailing spaces, which makes it easy to compare in tests.
+*/
+
+use crate::{find_set_bit, Color, Game, Piece, PieceType};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Glyphs {
+    Ascii,
+    Unicode
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RenderOptions {
+    pub glyphs: Glyphs,
+    pub labels: bool,
+    pub flipped: bool,
+    pub colors: bool,
+    pub highlights: bool
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions { glyphs: Glyphs::Ascii, labels: true, flipped: false, colors: false, highlights: true }
+    }
+}
+
+// 256 color ANSI escapes for the square backgrounds, and for the pieces standing on them
+const LIGHT_SQUARE: &str = "\x1b[48;5;187m";
+const DARK_SQUARE: &str = "\x1b[48;5;137m";
+const LAST_MOVE_SQUARE: &str = "\x1b[48;5;143m";
+const CHECK_SQUARE: &str = "\x1b[48;5;167m";
+const WHITE_PIECE: &str = "\x1b[1;97m";
+const BLACK_PIECE: &str = "\x1b[1;30m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Highlight {
+    None,
+    LastMove,
+    Check
+}
+
+fn glyph(piece: Option<Piece>, glyphs: Glyphs) -> char {
+    let piece = match piece {
+        Some(piece) => piece,
+        None => return if glyphs == Glyphs::Unicode { '·' } else { '.' }
+    };
+
+    match glyphs {
+        Glyphs::Ascii => piece.to_char(),
+        Glyphs::Unicode => match (piece.color, piece.piece_type) {
+            (Color::White, PieceType::King) => '♔',
+            (Color::White, PieceType::Queen) => '♕',
+            (Color::White, PieceType::Rook) => '♖',
+            (Color::White, PieceType::Bishop) => '♗',
+            (Color::White, PieceType::Knight) => '♘',
+            (Color::White, PieceType::Pawn) => '♙',
+            (Color::Black, PieceType::King) => '♚',
+            (Color::Black, PieceType::Queen) => '♛',
+            (Color::Black, PieceType::Rook) => '♜',
+            (Color::Black, PieceType::Bishop) => '♝',
+            (Color::Black, PieceType::Knight) => '♞',
+            (Color::Black, PieceType::Pawn) => '♟'
+        }
+    }
+}
+
+impl Game {
+
+    // Which squares to mark: where the last move came from and went to, and the king of the side to move if it is in check.
+    fn highlights(&self) -> [Highlight; 64] {
+        let mut highlights = [Highlight::None; 64];
+        if let Some(m) = self.last_move() {
+            highlights[m.from] = Highlight::LastMove;
+            highlights[m.to] = Highlight::LastMove;
+        }
+
+        let king = self.boards[self.active_color as usize][PieceType::King as usize];
+        if king != 0 && self.is_in_check() {
+            highlights[find_set_bit(king)] = Highlight::Check;
+        }
+        highlights
+    }
+
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let highlights = if options.highlights { self.highlights() } else { [Highlight::None; 64] };
+
+        // Top to bottom and left to right, as seen by whoever sits at the bottom
+        let ranks: Vec<usize> = if options.flipped { (0..8).collect() } else { (0..8).rev().collect() };
+        let files: Vec<usize> = if options.flipped { (0..8).rev().collect() } else { (0..8).collect() };
+
+        let mut board = String::new();
+        for &rank in &ranks {
+            let mut line = String::new();
+            if options.labels {
+                line.push_str(&format!("{} ", rank + 1));
+            }
+
+            for &file in &files {
+                let square = rank * 8 + file;
+                let piece = self.piece_at(square);
+                let glyph = glyph(piece, options.glyphs);
+
+                if options.colors {
+                    let background = match highlights[square] {
+                        Highlight::Check => CHECK_SQUARE,
+                        Highlight::LastMove => LAST_MOVE_SQUARE,
+                        // a1 is dark, and the colors alternate along ranks and files
+                        Highlight::None if (rank + file) % 2 == 0 => DARK_SQUARE,
+                        Highlight::None => LIGHT_SQUARE
+                    };
+                    let foreground = match piece {
+                        Some(Piece { color: Color::White, .. }) => WHITE_PIECE,
+                        _ => BLACK_PIECE
+                    };
+                    line.push_str(&format!("{}{} {} ", background, foreground, glyph));
+                } else {
+                    let (open, close) = match highlights[square] {
+                        Highlight::Check => ('[', ']'),
+                        Highlight::LastMove => ('(', ')'),
+                        Highlight::None => (' ', ' ')
+                    };
+                    line.push_str(&format!("{}{}{}", open, glyph, close));
+                }
+            }
+
+            if options.colors {
+                line.push_str(RESET);
+            }
+            board.push_str(line.trim_end());
+            board.push('\n');
+        }
+
+        if options.labels {
+            let mut line = "  ".to_string();
+            for &file in &files {
+                line.push_str(&format!(" {} ", crate::COL_MAP[file]));
+            }
+            board.push_str(line.trim_end());
+            board.push('\n');
+        }
+
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_after(moves: &str) -> Game {
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        for coordinates in moves.split_whitespace() {
+            let m = game.find_legal_move(coordinates).unwrap();
+            game.make_move(m);
+        }
+        game
+    }
+
+    #[test]
+    fn plain_board() {
+        let game = game_after("");
+        assert_eq!(
+            game.render(&RenderOptions::default()),
+            concat!(
+                "8  r  n  b  q  k  b  n  r\n",
+                "7  p  p  p  p  p  p  p  p\n",
+                "6  .  .  .  .  .  .  .  .\n",
+                "5  .  .  .  .  .  .  .  .\n",
+                "4  .  .  .  .  .  .  .  .\n",
+                "3  .  .  .  .  .  .  .  .\n",
+                "2  P  P  P  P  P  P  P  P\n",
+                "1  R  N  B  Q  K  B  N  R\n",
+                "   a  b  c  d  e  f  g  h\n"
+            )
+        );
+
+        let options = RenderOptions { labels: false, flipped: true, ..RenderOptions::default() };
+        let board = game.render(&options);
+        assert_eq!(board.lines().next(), Some(" R  N  B  K  Q  B  N  R"));
+        assert_eq!(board.lines().count(), 8);
+    }
+
+    #[test]
+    fn last_move_and_check() {
+        // 1. e4 f6 2. Qh5+: the queen's trip is marked, and so is the black king
+        let game = game_after("e2e4 f7f6 d1h5");
+        let board = game.render(&RenderOptions::default());
+        assert_eq!(board.lines().next(), Some("8  r  n  b  q [k] b  n  r"));
+        assert_eq!(board.lines().nth(3), Some("5  .  .  .  .  .  .  . (Q)"));
+        assert_eq!(board.lines().nth(7), Some("1  R  N  B (.) K  B  N  R"));
+
+        let options = RenderOptions { highlights: false, ..RenderOptions::default() };
+        assert!(!game.render(&options).contains(['(', '[']));
+    }
+
+    #[test]
+    fn unicode_pieces() {
+        let game = game_after("e2e4");
+        let options = RenderOptions { glyphs: Glyphs::Unicode, flipped: true, ..RenderOptions::default() };
+        let board = game.render(&options);
+        assert_eq!(board.lines().next(), Some("1  ♖  ♘  ♗  ♔  ♕  ♗  ♘  ♖"));
+        assert_eq!(board.lines().nth(1), Some("2  ♙  ♙  ♙ (·) ♙  ♙  ♙  ♙"));
+        assert_eq!(board.lines().nth(7), Some("8  ♜  ♞  ♝  ♚  ♛  ♝  ♞  ♜"));
+        assert_eq!(board.lines().nth(8), Some("   h  g  f  e  d  c  b  a"));
+    }
+
+    #[test]
+    fn ansi_colors() {
+        let game = game_after("g1f3");
+        let options = RenderOptions { colors: true, ..RenderOptions::default() };
+        let board = game.render(&options);
+
+        // a1 is dark, b1 light, and g1 was just left by the knight
+        let first_rank = board.lines().nth(7).unwrap();
+        assert!(first_rank.starts_with(&format!("1 {}{} R {}{} N ", DARK_SQUARE, WHITE_PIECE, LIGHT_SQUARE, WHITE_PIECE)));
+        assert!(first_rank.contains(&format!("{}{} . ", LAST_MOVE_SQUARE, BLACK_PIECE)));
+        assert!(first_rank.ends_with(RESET));
+        assert_eq!(board.lines().nth(8), Some("   a  b  c  d  e  f  g  h"));
+    }
+}
@@ -0,0 +1,386 @@
+/*
+    //////////////////////////////////////////
+    /// Standard Algebraic Notation (SAN)  ///
+    //////////////////////////////////////////
+
+    SAN is how humans (and PGN files) write moves: `e4`, `Nf3`, `exd5`, `O-O`, `e8=Q+`, `Nbd7`.
+    Unlike the coordinate notation UCI uses, a SAN move only makes sense together with the position it is played in:
+    - the moving piece is named by its letter (nothing for pawns), followed by the destination square,
+    - captures get an `x`, pawn captures start with the file the pawn came from (`exd5`),
+    - when two pieces of the same kind can reach the same square, just enough of the starting square is added to tell them
+      apart: the file if that does it (`Nbd7`), else the rank (`R1e2`), else both (`Qh4e1`),
+    - promotions end with `=` and the new piece, castling is `O-O` / `O-O-O`,
+    - a move that gives check ends with `+`, one that gives mate with `#`.
+
+    When parsing we are a bit more forgiving than when writing: check and mate suffixes (and `!`/`?` annotations) are
+    ignored, castling may be written with zeros, and the `=` of a promotion may be left out.
+*/
+
+use std::fmt;
+
+use crate::movegen::{Move, MoveKind};
+use crate::{square_name, Game, Piece, PieceType, COL_MAP};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SanErrorKind {
+    Malformed, // not SAN at all, e.g. "Xe4" or "e9"
+    Illegal, // proper SAN, but no legal move fits it
+    Ambiguous(Vec<String>) // more than one legal move fits it; these are the properly disambiguated candidates
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SanError {
+    pub san: String,
+    pub kind: SanErrorKind
+}
+
+impl SanError {
+    fn new(san: &str, kind: SanErrorKind) -> SanError {
+        SanError { san: san.to_string(), kind }
+    }
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            SanErrorKind::Malformed => write!(f, "Malformed move \"{}\"", self.san),
+            SanErrorKind::Illegal => write!(f, "Illegal move \"{}\"", self.san),
+            SanErrorKind::Ambiguous(candidates) => {
+                write!(f, "Ambiguous move \"{}\", could be {}", self.san, candidates.join(" or "))
+            }
+        }
+    }
+}
+
+fn piece_letter(piece_type: PieceType) -> Option<char> {
+    match piece_type {
+        PieceType::Pawn => None,
+        PieceType::Knight => Some('N'),
+        PieceType::Bishop => Some('B'),
+        PieceType::Rook => Some('R'),
+        PieceType::Queen => Some('Q'),
+        PieceType::King => Some('K')
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None
+    }
+}
+
+fn file_from_char(ch: char) -> Option<usize> {
+    ('a'..='h').contains(&ch).then(|| ch as usize - 'a' as usize)
+}
+
+fn rank_from_char(ch: char) -> Option<usize> {
+    ('1'..='8').contains(&ch).then(|| ch as usize - '1' as usize)
+}
+
+// What a SAN string says about the move it stands for. Anything left as None wasn't written down.
+struct SanMove {
+    piece_type: PieceType,
+    from_file: Option<usize>,
+    from_rank: Option<usize>,
+    to: usize,
+    promotion: Option<PieceType>
+}
+
+// Splits up everything but castling. Returns None if the string isn't SAN.
+fn parse_san_move(san: &str) -> Option<SanMove> {
+    let mut chars: Vec<char> = san.chars().collect();
+
+    // Promotion: "e8=Q" or "e8Q"
+    let mut promotion = None;
+    if let Some(&last) = chars.last() {
+        if let Some(piece_type) = piece_from_letter(last) {
+            if piece_type == PieceType::King {
+                return None;
+            }
+            promotion = Some(piece_type);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    let piece_type = match chars.first().copied().and_then(piece_from_letter) {
+        Some(piece_type) => {
+            chars.remove(0);
+            piece_type
+        },
+        None => PieceType::Pawn
+    };
+    if promotion.is_some() && piece_type != PieceType::Pawn {
+        return None;
+    }
+
+    // The destination square is always the last two characters
+    if chars.len() < 2 {
+        return None;
+    }
+    let to_rank = rank_from_char(chars.pop()?)?;
+    let to_file = file_from_char(chars.pop()?)?;
+
+    // Whatever is left is [file][rank][x], each of them optional
+    let mut rest = chars.into_iter().peekable();
+    let from_file = rest.next_if(|&ch| file_from_char(ch).is_some()).and_then(file_from_char);
+    let from_rank = rest.next_if(|&ch| rank_from_char(ch).is_some()).and_then(rank_from_char);
+    let capture = rest.next_if_eq(&'x').is_some();
+    if rest.next().is_some() {
+        return None;
+    }
+
+    // Pawns name their file when they capture ("exd5"), and only then
+    if piece_type == PieceType::Pawn && (from_rank.is_some() || capture != from_file.is_some()) {
+        return None;
+    }
+
+    Some(SanMove { piece_type, from_file, from_rank, to: to_rank * 8 + to_file, promotion })
+}
+
+impl Game {
+
+    fn moving_piece(&self, m: &Move) -> Piece {
+        match self.piece_at(m.from) {
+            Some(piece) => piece,
+            None => panic!("No piece on {}", square_name(m.from))
+        }
+    }
+
+    // Writes a legal move in SAN, including the check or mate suffix.
+    pub fn to_san(&self, m: Move) -> String {
+        let mut san = match m.kind {
+            MoveKind::KingsideCastle => "O-O".to_string(),
+            MoveKind::QueensideCastle => "O-O-O".to_string(),
+            _ => self.san_without_suffix(&m)
+        };
+
+        let mut after = self.clone();
+        after.make_move(m);
+        if after.is_checkmate() {
+            san.push('#');
+        } else if after.is_in_check() {
+            san.push('+');
+        }
+
+        san
+    }
+
+    fn san_without_suffix(&self, m: &Move) -> String {
+        let piece_type = self.moving_piece(m).piece_type;
+        let is_capture = matches!(m.kind, MoveKind::Capture | MoveKind::EnPassant);
+        let mut san = String::new();
+
+        match piece_letter(piece_type) {
+            None => {
+                if is_capture {
+                    san.push(COL_MAP[m.from % 8]);
+                }
+            },
+            Some(letter) => {
+                san.push(letter);
+
+                // Other pieces of the same kind that could go to the same square
+                let rivals: Vec<Move> = self.legal_moves()
+                    .into_iter()
+                    .filter(|other| {
+                        other.to == m.to && other.from != m.from && self.moving_piece(other).piece_type == piece_type
+                    })
+                    .collect();
+
+                if !rivals.is_empty() {
+                    let file_is_unique = rivals.iter().all(|other| other.from % 8 != m.from % 8);
+                    let rank_is_unique = rivals.iter().all(|other| other.from / 8 != m.from / 8);
+                    if file_is_unique {
+                        san.push(COL_MAP[m.from % 8]);
+                    } else if rank_is_unique {
+                        san.push_str(&(m.from / 8 + 1).to_string());
+                    } else {
+                        san.push_str(&square_name(m.from));
+                    }
+                }
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(m.to));
+
+        if let Some(letter) = m.promotion.and_then(piece_letter) {
+            san.push('=');
+            san.push(letter);
+        }
+
+        san
+    }
+
+    // Finds the legal move a SAN string stands for.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.legal_moves();
+
+        let castling = match text {
+            "O-O" | "0-0" => Some(MoveKind::KingsideCastle),
+            "O-O-O" | "0-0-0" => Some(MoveKind::QueensideCastle),
+            _ => None
+        };
+
+        let candidates: Vec<Move> = match castling {
+            Some(kind) => legal_moves.into_iter().filter(|m| m.kind == kind).collect(),
+            None => {
+                let wanted = parse_san_move(text).ok_or_else(|| SanError::new(san, SanErrorKind::Malformed))?;
+                legal_moves
+                    .into_iter()
+                    .filter(|m| {
+                        m.to == wanted.to
+                            && m.promotion == wanted.promotion
+                            && self.moving_piece(m).piece_type == wanted.piece_type
+                            && wanted.from_file.is_none_or(|file| m.from % 8 == file)
+                            && wanted.from_rank.is_none_or(|rank| m.from / 8 == rank)
+                    })
+                    .collect()
+            }
+        };
+
+        match candidates.as_slice() {
+            [] => Err(SanError::new(san, SanErrorKind::Illegal)),
+            [m] => Ok(*m),
+            _ => {
+                let mut names: Vec<String> = candidates.iter().map(|&m| self.to_san(m)).collect();
+                names.sort();
+                Err(SanError::new(san, SanErrorKind::Ambiguous(names)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_of(fen: &str, coordinates: &str) -> String {
+        let game = Game::read_FEN(fen).unwrap();
+        game.to_san(game.find_legal_move(coordinates).unwrap())
+    }
+
+    fn parse(fen: &str, san: &str) -> Result<String, SanErrorKind> {
+        let game = Game::read_FEN(fen).unwrap();
+        game.parse_san(san).map(|m| m.to_string()).map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn formats_moves() {
+        assert_eq!(san_of(crate::STARTING_FEN, "e2e4"), "e4");
+        assert_eq!(san_of(crate::STARTING_FEN, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san_of(kiwipete, "e2a6"), "Bxa6");
+        assert_eq!(san_of(kiwipete, "e5f7"), "Nxf7");
+
+        // En passant and promotions
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+        assert_eq!(san_of("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san_of("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8n"), "e8=N");
+        assert_eq!(san_of("3r1k2/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q"), "exd8=Q+");
+    }
+
+    #[test]
+    fn formats_check_and_mate() {
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a7"), "Ra7");
+        assert_eq!(san_of("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        // Fool's mate
+        assert_eq!(san_of("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2", "d8h4"), "Qh4#");
+    }
+
+    #[test]
+    fn disambiguates() {
+        // Knights on b8 and f6 can both go to d7: the file tells them apart
+        let knights = "rn2kb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(san_of(knights, "b8d7"), "Nbd7");
+        assert_eq!(san_of(knights, "f6d7"), "Nfd7");
+
+        // Rooks on e1 and e5 share the file, so the rank it is
+        let rooks = "k7/8/8/4R3/8/8/8/4R1K1 w - - 0 1";
+        assert_eq!(san_of(rooks, "e1e3"), "R1e3");
+        assert_eq!(san_of(rooks, "e5e3"), "R5e3");
+
+        // Three queens around e4: neither the file nor the rank alone is enough for the one on h4
+        let queens = "1k6/8/8/8/Q6Q/8/8/K6Q w - - 0 1";
+        assert_eq!(san_of(queens, "h4e4"), "Qh4e4");
+        assert_eq!(san_of(queens, "a4e4"), "Qae4");
+        assert_eq!(san_of(queens, "h1e4"), "Q1e4");
+
+        // A pinned knight doesn't count as a rival
+        assert_eq!(san_of("k7/8/8/8/8/2N1N3/8/4K3 w - - 0 1", "c3d5"), "Ncd5");
+        assert_eq!(san_of("k3r3/8/8/8/8/2N1N3/8/4K3 w - - 0 1", "c3d5"), "Nd5");
+    }
+
+    #[test]
+    fn parses_moves() {
+        assert_eq!(parse(crate::STARTING_FEN, "e4"), Ok("e2e4".to_string()));
+        assert_eq!(parse(crate::STARTING_FEN, "Nf3"), Ok("g1f3".to_string()));
+        assert_eq!(parse(crate::STARTING_FEN, "Nf3!?"), Ok("g1f3".to_string()));
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(parse(kiwipete, "O-O"), Ok("e1g1".to_string()));
+        assert_eq!(parse(kiwipete, "0-0-0"), Ok("e1c1".to_string()));
+        assert_eq!(parse(kiwipete, "dxe6"), Ok("d5e6".to_string()));
+        assert_eq!(parse(kiwipete, "Nxf7"), Ok("e5f7".to_string()));
+
+        let knights = "rn2kb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(parse(knights, "Nbd7"), Ok("b8d7".to_string()));
+        assert_eq!(parse(knights, "Nb8d7"), Ok("b8d7".to_string()));
+
+        assert_eq!(parse("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e8=Q"), Ok("e7e8q".to_string()));
+        assert_eq!(parse("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e8R"), Ok("e7e8r".to_string()));
+        assert_eq!(parse("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6"), Ok("e5d6".to_string()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let knights = "rn2kb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(parse(knights, "Nd7"), Err(SanErrorKind::Ambiguous(vec!["Nbd7".to_string(), "Nfd7".to_string()])));
+
+        assert_eq!(parse(crate::STARTING_FEN, "e5"), Err(SanErrorKind::Illegal));
+        assert_eq!(parse(crate::STARTING_FEN, "O-O"), Err(SanErrorKind::Illegal));
+        assert_eq!(parse(crate::STARTING_FEN, "Bc4"), Err(SanErrorKind::Illegal));
+        // Promotions have to say what they promote to
+        assert_eq!(parse("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e8"), Err(SanErrorKind::Illegal));
+
+        for malformed in ["", "Xe4", "e9", "Nf", "e2e4", "exd", "ed5", "Ne4=Q", "e8=K", "Nbb1d2", "hello"] {
+            assert_eq!(parse(crate::STARTING_FEN, malformed), Err(SanErrorKind::Malformed), "{}", malformed);
+        }
+
+        let game = Game::read_FEN(knights).unwrap();
+        assert_eq!(game.parse_san("Nd7").unwrap_err().to_string(), "Ambiguous move \"Nd7\", could be Nbd7 or Nfd7");
+        assert_eq!(game.parse_san("Ke4").unwrap_err().to_string(), "Illegal move \"Ke4\"");
+    }
+
+    #[test]
+    fn round_trips_every_legal_move() {
+        for fen in [
+            crate::STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "1k6/8/8/8/Q6Q/8/8/K6Q w - - 0 1"
+        ] {
+            let game = Game::read_FEN(fen).unwrap();
+            for m in game.legal_moves() {
+                let san = game.to_san(m);
+                assert_eq!(game.parse_san(&san), Ok(m), "{} in {}", san, fen);
+            }
+        }
+    }
+}
@@ -0,0 +1,803 @@
+/*
+    //////////////
+    /// Search ///
+    //////////////
+
+    Negamax with alpha-beta pruning, driven by iterative deepening.
+
+    Negamax: a position that is good for one side is exactly as bad for the other, so instead of separate "max" and "min"
+    players every node just returns the score from the point of view of the side to move, and the parent flips the sign.
+
+    Alpha-beta: alpha is the score the side to move is already guaranteed somewhere else in the tree, beta is the score the
+    opponent is guaranteed. As soon as a move scores >= beta the opponent will never allow this position, so the remaining
+    moves don't need to be looked at (a "cutoff").
+
+    Iterative deepening: search depth 1, then depth 2, then depth 3... until time runs out. It sounds wasteful, but every
+    iteration is much cheaper than the next one, we always have a finished answer ready to play, and the best line (the
+    "principal variation") of the previous iteration is searched first, which makes the cutoffs in the next one a lot better.
+    How much time there is, and when it has run out, is up to timeman.rs.
+
+    Transposition table: before searching a position we look it up in the table (see tt.rs). If it has been searched
+    deeply enough already, its score may settle the node right away; otherwise its best move is at least tried first.
+
+    Quiescence search: stopping dead at depth 0 and trusting the evaluation goes badly wrong in the middle of an exchange.
+    "Queen takes pawn" looks like a pawn won if the search ends before the recapture (the horizon effect). So at depth 0
+    we keep going with captures and promotions only (ordered MVV-LVA, see ordering.rs) until nothing is left to take.
+    The side to move doesn't have to capture though: it may "stand pat" and take the static evaluation instead, which is
+    also what ends the search in quiet positions. In check there is no standing pat, every way out gets searched.
+
+    Selective search: some moves and positions don't deserve the full depth. Each of these has a knob in `SearchParams`
+    (and a UCI option, so they can be tuned in self-play); setting it to 0 switches that technique off.
+
+    - Null-move pruning: if we could pass and the opponent still can't get below beta in a reduced search, a real move will
+      do at least as well, so the node is cut off. Passing is only a good guess when there is something better to do,
+      which is why it isn't tried in check (passing is illegal) or with only king and pawns left (zugzwang is common).
+    - Late move reductions: quiet moves that come late in the move ordering rarely turn out best. They get searched a ply
+      shallower with a null window first, and only if that comes out above alpha again at full depth.
+    - Futility pruning: one or two plies from the horizon, if the static evaluation plus a margin can't reach alpha, quiet
+      moves that don't give check are skipped; they are very unlikely to make up the difference.
+    - Reverse futility pruning: the other way round, if the evaluation minus a margin is still at least beta close to the
+      horizon, the node is cut off without searching it.
+
+    None of them apply at the root, in check, or along the previous iteration's best line.
+
+    Draws: a position that repeats one from earlier in the game (or the search), that can't be won by anyone anymore, or
+    that has hit the fifty move rule scores 0, no matter what the evaluation thinks of it.
+*/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::eval::evaluate;
+use crate::movegen::Move;
+use crate::ordering::{is_tactical, order_by_mvv_lva, MoveOrdering};
+use crate::timeman::{Clock, SystemClock, TimeBudget, TimeManager};
+use crate::tt::{Bound, TranspositionTable, DEFAULT_HASH_MB};
+use crate::{Color, Game, PieceType};
+
+pub const INFINITY: i32 = 32000;
+// Being mated `ply` half moves from the root scores -(MATE - ply), so quicker mates score better than slower ones.
+pub const MATE: i32 = 30000;
+pub const MAX_PLY: usize = 128;
+
+// Anything this close to MATE can only be a mate score
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY as i32
+}
+
+// Full moves until mate, the way UCI's "score mate N" wants it: positive when we mate, negative when we get mated.
+pub fn mate_in(score: i32) -> i32 {
+    if score > 0 {
+        (MATE - score + 1) / 2
+    } else {
+        -(MATE + score) / 2
+    }
+}
+
+// Mate scores count plies from the root, but a table entry can be reached at any ply. In the table they are stored
+// relative to the position itself ("mate in N from here") and converted back on the way out.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// The knobs of the selective search (see the top of this file). 0 switches a technique off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchParams {
+    pub null_move_reduction: i32, // plies, on top of the one the null move itself takes
+    pub late_move_threshold: i32, // moves searched at full depth before the reductions start
+    pub futility_margin: i32, // centipawns per ply of depth left
+    pub reverse_futility_margin: i32 // centipawns per ply of depth left
+}
+
+impl Default for SearchParams {
+    fn default() -> SearchParams {
+        SearchParams { null_move_reduction: 2, late_move_threshold: 4, futility_margin: 150, reverse_futility_margin: 100 }
+    }
+}
+
+impl SearchParams {
+
+    // A plain alpha-beta search, everything looked at to full depth
+    pub const OFF: SearchParams =
+        SearchParams { null_move_reduction: 0, late_move_threshold: 0, futility_margin: 0, reverse_futility_margin: 0 };
+
+    // Names (as UCI options) and the largest value that makes sense for each
+    pub const OPTIONS: [(&'static str, i32); 4] =
+        [("NullMoveReduction", 4), ("LateMoveThreshold", 64), ("FutilityMargin", 1000), ("ReverseFutilityMargin", 1000)];
+
+    // The parameter called `name` in `OPTIONS`, ignoring case.
+    pub fn by_name(&mut self, name: &str) -> Option<&mut i32> {
+        match name.to_ascii_lowercase().as_str() {
+            "nullmovereduction" => Some(&mut self.null_move_reduction),
+            "latemovethreshold" => Some(&mut self.late_move_threshold),
+            "futilitymargin" => Some(&mut self.futility_margin),
+            "reversefutilitymargin" => Some(&mut self.reverse_futility_margin),
+            _ => None
+        }
+    }
+}
+
+// When to stop searching. With no limits at all the search only stops once `stop` is set (or at MAX_PLY).
+#[derive(Debug, Default, Clone)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub budget: Option<TimeBudget>,
+    // What the budget is measured with. Without one, the time counts from the start of the search.
+    pub clock: Option<Arc<dyn Clock>>,
+    pub nodes: Option<u64>,
+    pub stop: Option<Arc<AtomicBool>>,
+    // Not a limit as such, but how the search spends the depth it has
+    pub params: SearchParams
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: usize,
+    pub pv: Vec<Move>,
+    pub nodes: u64
+}
+
+// Zugzwang, where passing would be the best move if only it were allowed, mostly happens with just king and pawns left.
+fn has_non_pawn_material(game: &Game, color: Color) -> bool {
+    let boards = &game.boards[color as usize];
+    [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen]
+        .iter()
+        .any(|&piece_type| boards[piece_type as usize] != 0)
+}
+
+struct Searcher<'a> {
+    limits: &'a SearchLimits,
+    tt: &'a mut TranspositionTable,
+    nodes: u64,
+    aborted: bool,
+    // Triangular PV table: pv[ply] holds the best line found from `ply` onwards in the node currently being searched there
+    pv: Vec<Vec<Move>>,
+    // The PV of the previous iteration, tried first while we are still walking down it
+    previous_pv: Vec<Move>,
+    ordering: MoveOrdering,
+    // null_moves[ply] is set while the move played at `ply` is a null move
+    null_moves: Vec<bool>,
+    clock: Arc<dyn Clock>,
+    time: Option<TimeManager>
+}
+
+impl Searcher<'_> {
+
+    // Node, stop and time checks. Looking at the clock costs something, so that one is only done every 1024 nodes.
+    fn should_stop(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+
+        if let Some(limit) = self.limits.nodes {
+            if self.nodes >= limit {
+                self.aborted = true;
+            }
+        }
+
+        if let Some(stop) = &self.limits.stop {
+            if stop.load(Ordering::Relaxed) {
+                self.aborted = true;
+            }
+        }
+
+        if self.nodes.is_multiple_of(1024) {
+            if let Some(time) = &self.time {
+                if time.hard_limit_reached(self.clock.elapsed()) {
+                    self.aborted = true;
+                }
+            }
+        }
+
+        self.aborted
+    }
+
+    fn negamax(&mut self, game: &mut Game, depth: usize, ply: usize, mut alpha: i32, beta: i32, on_pv: bool) -> i32 {
+        if depth == 0 {
+            return self.quiescence(game, ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+        self.pv[ply].clear();
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        // Not at the root though: there we still want a move, even in a drawn position
+        if ply > 0 && (game.is_repetition() || game.has_insufficient_material()) {
+            return 0;
+        }
+
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(game.hash) {
+            tt_move = entry.best_move;
+
+            // Never at the root: we need a move to play, not just a score. An exact score inside the window would end
+            // our principal variation right here, so those nodes are searched anyway (with the best move first).
+            if ply > 0 && entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                let cutoff = match entry.bound {
+                    Bound::Exact => score <= alpha || score >= beta,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha
+                };
+                if cutoff {
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = game.legal_moves();
+        if moves.is_empty() {
+            return if game.is_in_check() { -MATE + ply as i32 } else { 0 };
+        }
+
+        // Checked after looking for mate, since a mate on the hundredth half move still counts
+        if ply > 0 && game.halfmove_clock >= 100 {
+            return 0;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return evaluate(game);
+        }
+
+        let params = self.limits.params;
+        let in_check = game.is_in_check();
+        let prunable = ply > 0 && !in_check && !on_pv;
+        let static_eval = if prunable { evaluate(game) } else { 0 };
+
+        // Reverse futility pruning: so far ahead that a few plies won't bring us back down to beta
+        if prunable && params.reverse_futility_margin > 0 && depth <= 3 && !is_mate_score(beta) {
+            let score = static_eval - params.reverse_futility_margin * depth as i32;
+            if score >= beta {
+                return score;
+            }
+        }
+
+        // Null-move pruning: even passing is good enough. Not twice in a row though, that would only search the same
+        // position again with less depth.
+        let reduction = params.null_move_reduction.max(0) as usize;
+        if prunable
+            && !self.null_moves[ply - 1]
+            && reduction > 0
+            && depth > reduction
+            && static_eval >= beta
+            && !is_mate_score(beta)
+            && has_non_pawn_material(game, game.active_color)
+        {
+            let undo = game.make_null_move();
+            self.null_moves[ply] = true;
+            let score = -self.negamax(game, depth - 1 - reduction, ply + 1, -beta, -beta + 1, false);
+            self.null_moves[ply] = false;
+            game.unmake_null_move(undo);
+
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+        }
+
+        // Futility pruning: the quiet moves can't make up what we are short of alpha
+        let futile = prunable
+            && params.futility_margin > 0
+            && depth <= 2
+            && !is_mate_score(alpha)
+            && static_eval + params.futility_margin * depth as i32 <= alpha;
+
+        self.ordering.order(game, &mut moves, tt_move, ply);
+
+        // Walking down last iteration's best line: try its move first
+        let mut follow_pv = false;
+        if on_pv {
+            if let Some(pv_move) = self.previous_pv.get(ply) {
+                if let Some(index) = moves.iter().position(|m| m == pv_move) {
+                    moves[..=index].rotate_right(1);
+                    follow_pv = true;
+                }
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best_move = None;
+        for (i, m) in moves.into_iter().enumerate() {
+            game.make_move(m);
+            // Checks are left alone as well, they are too often what the position is about
+            let quiet = !is_tactical(&m) && !game.is_in_check();
+
+            // The first move is always searched, so there is a score to return
+            if futile && quiet && i > 0 {
+                game.unmake_move();
+                continue;
+            }
+
+            // Late move reductions: a quick look first, the full search only if the move beats alpha after all
+            let reduce = params.late_move_threshold > 0
+                && i >= params.late_move_threshold as usize
+                && depth >= 3
+                && quiet
+                && !in_check;
+            let mut score = if reduce {
+                -self.negamax(game, depth - 2, ply + 1, -alpha - 1, -alpha, false)
+            } else {
+                INFINITY
+            };
+            if score > alpha {
+                score = -self.negamax(game, depth - 1, ply + 1, -beta, -alpha, follow_pv && i == 0);
+            }
+            game.unmake_move();
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(m);
+
+                let (head, tail) = self.pv.split_at_mut(ply + 1);
+                head[ply].clear();
+                head[ply].push(m);
+                head[ply].extend_from_slice(&tail[0]);
+
+                if score >= beta {
+                    self.ordering.record_cutoff(game, m, depth, ply);
+                    break;
+                }
+            }
+        }
+
+        let bound = if alpha >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(game.hash, depth, score_to_tt(alpha, ply), bound, best_move);
+
+        alpha
+    }
+
+    fn quiescence(&mut self, game: &mut Game, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.pv[ply].clear();
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        // The root is the caller's business: it is the position we were asked about. Captures can't repeat a position,
+        // but the evasions we try when in check can be quiet moves, so every deeper node still has to look.
+        if ply > 0 && (game.is_repetition() || game.has_insufficient_material()) {
+            return 0;
+        }
+
+        // In check every evasion has to be tried, otherwise only captures and promotions. No capture doesn't mean no move
+        // though, so before calling it stalemate we ask whether there is a quiet one.
+        let in_check = game.is_in_check();
+        let mut moves = if in_check { game.legal_moves() } else { game.legal_captures() };
+        if moves.is_empty() && (in_check || !game.has_legal_move()) {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+
+        if ply > 0 && game.halfmove_clock >= 100 {
+            return 0;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return evaluate(game);
+        }
+
+        if !in_check {
+            let stand_pat = evaluate(game);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+
+            // Underpromotions are left to the main search, here they would only ever be worse than a queen
+            moves.retain(|m| m.promotion.is_none_or(|piece_type| piece_type == PieceType::Queen));
+        }
+        order_by_mvv_lva(game, &mut moves);
+
+        for m in moves {
+            game.make_move(m);
+            let score = -self.quiescence(game, ply + 1, -beta, -alpha);
+            game.unmake_move();
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > alpha {
+                alpha = score;
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+
+        alpha
+    }
+}
+
+// Iterative deepening search. `progress` gets called after every completed iteration, which is how UCI prints its "info" lines.
+// The transposition table is passed in so that it can be kept from one move to the next.
+pub fn search_with_progress(
+    game: &mut Game,
+    limits: &SearchLimits,
+    tt: &mut TranspositionTable,
+    progress: &mut dyn FnMut(&SearchResult)
+) -> SearchResult {
+    iterative_deepening(game, limits, tt, MoveOrdering::new(true), progress)
+}
+
+fn iterative_deepening(
+    game: &mut Game,
+    limits: &SearchLimits,
+    tt: &mut TranspositionTable,
+    ordering: MoveOrdering,
+    progress: &mut dyn FnMut(&SearchResult)
+) -> SearchResult {
+    tt.new_search();
+    let mut searcher = Searcher {
+        limits,
+        tt,
+        nodes: 0,
+        aborted: false,
+        pv: vec![Vec::new(); MAX_PLY],
+        previous_pv: Vec::new(),
+        ordering,
+        null_moves: vec![false; MAX_PLY],
+        clock: limits.clock.clone().unwrap_or_else(|| Arc::new(SystemClock::new(Instant::now()))),
+        time: limits.budget.map(TimeManager::new)
+    };
+
+    // Whatever happens, have some legal move ready to play
+    let mut result = SearchResult {
+        best_move: game.legal_moves().first().copied(),
+        ..SearchResult::default()
+    };
+    if result.best_move.is_none() {
+        result.score = if game.is_in_check() { -MATE } else { 0 };
+        return result;
+    }
+
+    let max_depth = limits.depth.unwrap_or(MAX_PLY - 1).min(MAX_PLY - 1);
+    for depth in 1..=max_depth {
+        let score = searcher.negamax(game, depth, 0, -INFINITY, INFINITY, true);
+
+        // A half finished iteration can't be trusted: it might not have looked at the move that refutes its "best" move
+        if searcher.aborted {
+            break;
+        }
+
+        searcher.previous_pv = searcher.pv[0].clone();
+        result = SearchResult {
+            best_move: searcher.pv[0].first().copied(),
+            score,
+            depth,
+            pv: searcher.pv[0].clone(),
+            nodes: searcher.nodes
+        };
+        progress(&result);
+
+        // Rather than start an iteration we won't finish, play now and keep the time for later
+        if let Some(time) = &mut searcher.time {
+            time.iteration_done(result.best_move, score);
+            if time.soft_limit_reached(searcher.clock.elapsed()) {
+                break;
+            }
+        }
+
+        // Once a forced mate has been found, deeper searches can't find anything better
+        if is_mate_score(score) && mate_in(score).unsigned_abs() as usize * 2 <= depth {
+            break;
+        }
+    }
+
+    result.nodes = searcher.nodes;
+    result
+}
+
+pub fn search(game: &mut Game, limits: &SearchLimits) -> SearchResult {
+    let mut tt = TranspositionTable::new(DEFAULT_HASH_MB);
+    search_with_progress(game, limits, &mut tt, &mut |_| ())
+}
+
+// A mix of openings, middlegames and endgames, for `chessengine bench`
+pub const BENCH_POSITIONS: [&str; 6] = [
+    crate::STARTING_FEN,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"
+];
+
+// Searches every bench position to `depth`, each with an empty table, and returns how many nodes that took.
+// With `heuristics` off only the hash move goes first and the captures are ordered by MVV-LVA, the way the search did
+// before killers and history, which shows what those save. `SearchParams::OFF` does the same for the selective search.
+pub fn bench(depth: usize, heuristics: bool, params: SearchParams) -> Vec<u64> {
+    let limits = SearchLimits { depth: Some(depth), params, ..SearchLimits::default() };
+    BENCH_POSITIONS
+        .iter()
+        .map(|fen| {
+            let mut game = Game::read_FEN(fen).expect("the bench positions are valid FENs");
+            let mut tt = TranspositionTable::new(DEFAULT_HASH_MB);
+            iterative_deepening(&mut game, &limits, &mut tt, MoveOrdering::new(heuristics), &mut |_| ()).nodes
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeman::MockClock;
+    use std::time::Duration;
+
+    fn search_fen(fen: &str, depth: usize) -> SearchResult {
+        let mut game = Game::read_FEN(fen).unwrap();
+        let limits = SearchLimits { depth: Some(depth), ..SearchLimits::default() };
+        search(&mut game, &limits)
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        // Back rank mate: Ra8#
+        let result = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "a1a8");
+        assert_eq!(result.score, MATE - 1);
+        assert_eq!(mate_in(result.score), 1);
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        // 1. Kb6 Kb8 2. Rh8#
+        let result = search_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1", 4);
+        assert_eq!(mate_in(result.score), 2);
+        assert_eq!(result.pv.len(), 3);
+    }
+
+    #[test]
+    fn sees_getting_mated() {
+        // Black's only move is Kb8, then Rh8 is mate
+        let result = search_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1", 4);
+        assert!(is_mate_score(result.score));
+        assert!(result.score < 0);
+        assert_eq!(mate_in(result.score), -1);
+    }
+
+    #[test]
+    fn wins_material() {
+        // The knight on d5 is hanging
+        let result = search_fen("4k3/8/8/3n4/8/8/3R4/4K3 w - - 0 1", 2);
+        assert_eq!(result.best_move.unwrap().to_string(), "d2d5");
+        assert!(result.score > 400);
+    }
+
+    #[test]
+    fn does_not_grab_a_defended_pawn() {
+        // Qxd5 wins a pawn at depth 1, until the quiescence search sees exd5
+        let result = search_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", 1);
+        assert_ne!(result.best_move.unwrap().to_string(), "d1d5");
+        assert!(result.score > 500 && result.score < 900, "{}", result.score);
+
+        // A pawn that isn't defended is fine to take
+        let result = search_fen("4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1", 1);
+        assert_eq!(result.best_move.unwrap().to_string(), "d1d5");
+    }
+
+    fn quiescence_fen(fen: &str) -> (i32, i32) {
+        let mut game = Game::read_FEN(fen).unwrap();
+        let limits = SearchLimits::default();
+        let mut tt = TranspositionTable::new(1);
+        let mut searcher = Searcher {
+            limits: &limits,
+            tt: &mut tt,
+            nodes: 0,
+            aborted: false,
+            pv: vec![Vec::new(); MAX_PLY],
+            previous_pv: Vec::new(),
+            ordering: MoveOrdering::new(true),
+            null_moves: vec![false; MAX_PLY],
+            clock: Arc::new(SystemClock::new(Instant::now())),
+            time: None
+        };
+        (searcher.quiescence(&mut game, 0, -INFINITY, INFINITY), evaluate(&game))
+    }
+
+    #[test]
+    fn quiescence_resolves_captures() {
+        // Nothing to take: the evaluation stands
+        let (score, static_score) = quiescence_fen(crate::STARTING_FEN);
+        assert_eq!(score, static_score);
+
+        // A queen up on the board, but the pawn on e3 takes it
+        let (score, static_score) = quiescence_fen("4k3/8/8/8/3q4/4P3/8/4K3 w - - 0 1");
+        assert!(static_score < -700);
+        assert!(score > 0, "{}", score);
+
+        // Rook takes rook, and then the other rook takes back: nothing gained, so better not start
+        let (score, static_score) = quiescence_fen("3rk3/3r4/8/8/8/8/3R4/4K3 w - - 0 1");
+        assert_eq!(score, static_score);
+
+        // Mated with nothing to capture
+        let (score, _) = quiescence_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(score, -MATE);
+
+        // Stalemated with nothing to capture is a draw, not a stand pat a queen down
+        let (score, static_score) = quiescence_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(score, 0);
+        assert!(static_score < 0);
+    }
+
+    #[test]
+    fn pv_starts_with_best_move_and_is_legal() {
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let limits = SearchLimits { depth: Some(3), ..SearchLimits::default() };
+        let mut depths = Vec::new();
+        let mut tt = TranspositionTable::new(1);
+        let result = search_with_progress(&mut game, &limits, &mut tt, &mut |info| depths.push(info.depth));
+
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+        for m in &result.pv {
+            assert!(game.legal_moves().contains(m));
+            game.make_move(*m);
+        }
+    }
+
+    #[test]
+    fn respects_node_limit_and_stop_flag() {
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let limits = SearchLimits { nodes: Some(5000), ..SearchLimits::default() };
+        let result = search(&mut game, &limits);
+        assert!(result.nodes <= 5001);
+        assert!(result.best_move.is_some());
+
+        let stop = Arc::new(AtomicBool::new(true));
+        let limits = SearchLimits { stop: Some(stop), ..SearchLimits::default() };
+        let result = search(&mut game, &limits);
+        assert!(result.best_move.is_some());
+        assert_eq!(result.depth, 0);
+    }
+
+    #[test]
+    fn stops_at_the_hard_limit() {
+        // Every look at the clock costs a millisecond, and the clock is looked at every 1024 nodes
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let budget = TimeBudget { soft: Duration::from_secs(60), hard: Duration::from_millis(10) };
+        let clock = Arc::new(MockClock::new(1));
+        let limits = SearchLimits { budget: Some(budget), clock: Some(clock.clone()), ..SearchLimits::default() };
+        let result = search(&mut game, &limits);
+
+        // Right in the middle of an iteration, but with a move from the last finished one
+        assert!(result.nodes <= 12 * 1024, "{}", result.nodes);
+        assert!(result.depth > 0);
+        assert!(game.legal_moves().contains(&result.best_move.unwrap()));
+        assert!(clock.elapsed() <= Duration::from_millis(12));
+
+        // Without any time left there is still a move to play
+        let limits = SearchLimits { budget: Some(TimeBudget::fixed(Duration::ZERO)), clock: Some(clock), ..limits };
+        assert!(search(&mut game, &limits).best_move.is_some());
+    }
+
+    #[test]
+    fn no_new_iteration_after_the_soft_limit() {
+        // The clock says 0 after the first iteration and 5 ms after the second, well past the soft limit even when
+        // stretched for a change of the best move
+        let mut game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let budget = TimeBudget { soft: Duration::from_millis(1), hard: Duration::from_secs(60) };
+        let clock = Arc::new(MockClock::new(5));
+        let limits = SearchLimits { budget: Some(budget), clock: Some(clock), ..SearchLimits::default() };
+        let mut depths = Vec::new();
+        let mut tt = TranspositionTable::new(1);
+        search_with_progress(&mut game, &limits, &mut tt, &mut |info| depths.push(info.depth));
+        assert_eq!(depths, vec![1, 2]);
+    }
+
+    #[test]
+    fn transposition_table_saves_work() {
+        let limits = SearchLimits { depth: Some(4), ..SearchLimits::default() };
+        let mut game = Game::read_FEN("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut tt = TranspositionTable::new(1);
+
+        let first = search_with_progress(&mut game, &limits, &mut tt, &mut |_| ());
+        // Searching the same position again with the table still filled is a lot cheaper and gives the same answer
+        let second = search_with_progress(&mut game, &limits, &mut tt, &mut |_| ());
+        assert!(second.nodes < first.nodes / 2, "{} vs {}", second.nodes, first.nodes);
+        assert_eq!(second.score, first.score);
+        assert_eq!(second.best_move, first.best_move);
+    }
+
+    #[test]
+    fn move_ordering_shrinks_the_tree() {
+        // Killers and history on top of the hash move and MVV-LVA: not every single position gains, but all together the
+        // tree gets smaller (and more so the deeper it goes)
+        let plain: u64 = bench(4, false, SearchParams::OFF).iter().sum();
+        let ordered: u64 = bench(4, true, SearchParams::OFF).iter().sum();
+        assert!(ordered * 10 < plain * 9, "{} vs {}", ordered, plain);
+    }
+
+    #[test]
+    fn selective_search_shrinks_the_tree() {
+        // `chessengine bench` shows the same at depth 5
+        let full: u64 = bench(4, true, SearchParams::OFF).iter().sum();
+        let selective: u64 = bench(4, true, SearchParams::default()).iter().sum();
+        assert!(selective * 2 < full, "{} vs {}", selective, full);
+    }
+
+    #[test]
+    fn no_null_move_with_only_pawns() {
+        // Kings and pawns only (white happens to be stalemated here): neither side may pass
+        let game = Game::read_FEN("8/8/8/8/8/1k6/1p6/1K6 w - - 0 1").unwrap();
+        assert!(!has_non_pawn_material(&game, Color::White));
+        assert!(!has_non_pawn_material(&game, Color::Black));
+        let game = Game::read_FEN("4k3/8/8/8/8/8/5P2/4K1N1 w - - 0 1").unwrap();
+        assert!(has_non_pawn_material(&game, Color::White));
+        assert!(!has_non_pawn_material(&game, Color::Black));
+    }
+
+    #[test]
+    fn zugzwang_in_a_pawn_ending() {
+        // A trebuchet: each king guards its own pawn and attacks the other one. Whoever has to move gives up their pawn,
+        // which is exactly what a null move would pretend isn't so.
+        for fen in ["8/8/8/4pK2/3kP3/8/8/8 w - - 0 1", "8/8/8/4pK2/3kP3/8/8/8 b - - 0 1"] {
+            let result = search_fen(fen, 6);
+            assert!(result.score < -100, "{}: {}", fen, result.score);
+        }
+
+        // With a spare pawn move white can hand the move over, and wins the e5 pawn
+        let result = search_fen("8/8/8/4pK2/3kP3/8/P7/8 w - - 0 1", 6);
+        assert!(["a2a3", "a2a4"].contains(&result.best_move.unwrap().to_string().as_str()));
+        assert!(result.score > 100, "{}", result.score);
+    }
+
+    #[test]
+    fn mate_scores_survive_the_table() {
+        assert_eq!(score_from_tt(score_to_tt(MATE - 7, 4), 4), MATE - 7);
+        assert_eq!(score_from_tt(score_to_tt(-MATE + 6, 2), 2), -MATE + 6);
+        // Mate in 3 plies from a node at ply 4 is mate in 5 from a node at ply 2 (seen from the root)
+        assert_eq!(score_from_tt(score_to_tt(MATE - 7, 4), 2), MATE - 5);
+        assert_eq!(score_to_tt(35, 10), 35);
+    }
+
+    #[test]
+    fn takes_a_perpetual_check_when_behind() {
+        // Two queens down, but Qe8+ Kh7 Qh5+ Kg8 repeats the position forever
+        let result = search_fen("6k1/6p1/8/7Q/2K5/8/8/qq6 w - - 0 1", 4);
+        assert_eq!(result.best_move.unwrap().to_string(), "h5e8");
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn no_moves_left() {
+        let result = search_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+}
@@ -0,0 +1,227 @@
+/*
+    ///////////////////////
+    /// Time management ///
+    ///////////////////////
+
+    With a clock running, the GUI tells us how much time each side has left (wtime/btime), what gets added after every
+    move (winc/binc) and sometimes how many moves are left until the next time control (movestogo). From that we work out
+    two limits for the move:
+
+    - the soft limit, what we would like to spend: an even share of the time left over the moves still to play, plus most
+      of the increment. Once it has passed no new iteration is started, but the one running gets to finish.
+    - the hard limit, which is never crossed: the search stops in the middle of an iteration and plays the best move of
+      the last one it finished. A few times the soft limit, but never more than half of what is left on the clock (all
+      of it, minus the overhead, if this is the last move before the time control).
+
+    The soft limit stretches when the search is unsure of itself. If the best move keeps changing from one iteration to
+    the next, or the score drops, something is going on in the position and a bit more time is well spent. It never
+    stretches past the hard limit though.
+
+    `MOVE_OVERHEAD` is always kept back, for the time our answer spends getting to the GUI. Whatever happens we don't lose
+    on time.
+
+    The search reads the time through a `Clock`. The real one counts from an `Instant`. The tests use `MockClock` instead,
+    which moves on by a fixed step every time it is read, so they never have to wait for anything.
+*/
+
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+use crate::movegen::Move;
+
+pub const MOVE_OVERHEAD: u64 = 50; // milliseconds
+// Without movestogo we plan as if the game went on for this many more moves
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const HARD_LIMIT_FACTOR: u64 = 4;
+// A score this much (in centipawns) below the previous iteration's counts as a drop
+const SCORE_DROP: i32 = 30;
+
+// Time since the search started.
+pub trait Clock: Debug + Send + Sync {
+    fn elapsed(&self) -> Duration;
+}
+
+#[derive(Debug)]
+pub struct SystemClock {
+    start: Instant
+}
+
+impl SystemClock {
+    pub fn new(start: Instant) -> SystemClock {
+        SystemClock { start }
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// Starts at 0 and moves on by `step` milliseconds every time it is read.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock {
+    millis: std::sync::atomic::AtomicU64,
+    step: u64
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(step: u64) -> MockClock {
+        MockClock { millis: std::sync::atomic::AtomicU64::new(0), step }
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.millis.fetch_add(self.step, std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration
+}
+
+impl TimeBudget {
+
+    // "go movetime": exactly this long, no matter what
+    pub fn fixed(time: Duration) -> TimeBudget {
+        TimeBudget { soft: time, hard: time }
+    }
+
+    // The budget for the side to move, from its clock. All in milliseconds.
+    pub fn from_clock(time: u64, increment: u64, moves_to_go: Option<u64>) -> TimeBudget {
+        let available = time.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+        // With more moves to go before the time control, the next ones need some time too
+        let most = if moves_to_go == 1 { available } else { available / 2 };
+        let soft = (available / moves_to_go + increment * 3 / 4).min(most);
+        let hard = (soft * HARD_LIMIT_FACTOR).min(most);
+
+        TimeBudget { soft: Duration::from_millis(soft), hard: Duration::from_millis(hard) }
+    }
+}
+
+// Decides, after every finished iteration, whether there is time for another one.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    budget: TimeBudget,
+    // Goes up by one every time the best move changes, and halves with every iteration
+    instability: f64,
+    score_dropped: bool,
+    last: Option<(Move, i32)> // best move and score of the last iteration
+}
+
+impl TimeManager {
+
+    pub fn new(budget: TimeBudget) -> TimeManager {
+        TimeManager { budget, instability: 0.0, score_dropped: false, last: None }
+    }
+
+    pub fn iteration_done(&mut self, best_move: Option<Move>, score: i32) {
+        self.instability /= 2.0;
+        self.score_dropped = false;
+
+        if let (Some((last_move, last_score)), Some(best_move)) = (self.last, best_move) {
+            if best_move != last_move {
+                self.instability += 1.0;
+            }
+            self.score_dropped = score <= last_score - SCORE_DROP;
+        }
+        self.last = best_move.map(|m| (m, score));
+    }
+
+    // The soft limit, stretched by how unsure the search is right now
+    pub fn soft_limit(&self) -> Duration {
+        let mut scale = 1.0 + self.instability;
+        if self.score_dropped {
+            scale *= 1.5;
+        }
+        self.budget.soft.mul_f64(scale).min(self.budget.hard)
+    }
+
+    // No new iteration after this
+    pub fn soft_limit_reached(&self, elapsed: Duration) -> bool {
+        elapsed >= self.soft_limit()
+    }
+
+    // Not even the current iteration gets finished after this
+    pub fn hard_limit_reached(&self, elapsed: Duration) -> bool {
+        elapsed >= self.budget.hard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    fn millis(budget: TimeBudget) -> (u128, u128) {
+        (budget.soft.as_millis(), budget.hard.as_millis())
+    }
+
+    #[test]
+    fn budget_from_the_clock() {
+        // A minute left, no increment: a 30th of it, and at most four times that
+        assert_eq!(millis(TimeBudget::from_clock(60_050, 0, None)), (2000, 8000));
+        // Most of the increment comes on top
+        assert_eq!(millis(TimeBudget::from_clock(30_050, 2000, None)), (2500, 10_000));
+        // The last move before the time control may use everything but the overhead
+        assert_eq!(millis(TimeBudget::from_clock(10_050, 0, Some(1))), (10_000, 10_000));
+        // Otherwise the hard limit keeps half of the clock for the moves after this one
+        assert_eq!(millis(TimeBudget::from_clock(10_050, 0, Some(3))), (3333, 5000));
+        // Next to nothing left: don't think at all, just answer
+        assert_eq!(millis(TimeBudget::from_clock(40, 0, None)), (0, 0));
+        // The increment can't be spent before it has been added
+        assert_eq!(millis(TimeBudget::from_clock(1050, 5000, None)), (500, 500));
+    }
+
+    #[test]
+    fn budget_never_flags() {
+        for time in [0, 1, 49, 50, 51, 100, 1000, 12_345, 600_000] {
+            for increment in [0, 100, 5000] {
+                for moves_to_go in [None, Some(1), Some(2), Some(40)] {
+                    let budget = TimeBudget::from_clock(time, increment, moves_to_go);
+                    assert!(budget.soft <= budget.hard);
+                    assert!(budget.hard.as_millis() as u64 + MOVE_OVERHEAD <= time.max(MOVE_OVERHEAD));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsure_searches_get_more_time() {
+        let game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let e4 = game.find_legal_move("e2e4");
+        let d4 = game.find_legal_move("d2d4");
+        let budget = TimeBudget { soft: Duration::from_millis(100), hard: Duration::from_millis(400) };
+        let mut time = TimeManager::new(budget);
+
+        time.iteration_done(e4, 20);
+        time.iteration_done(e4, 25);
+        assert_eq!(time.soft_limit(), Duration::from_millis(100));
+        assert!(!time.soft_limit_reached(Duration::from_millis(99)));
+        assert!(time.soft_limit_reached(Duration::from_millis(100)));
+
+        // The best move changed: twice the time, and once it stays put that wears off again
+        time.iteration_done(d4, 25);
+        assert_eq!(time.soft_limit(), Duration::from_millis(200));
+        time.iteration_done(d4, 25);
+        assert_eq!(time.soft_limit(), Duration::from_millis(150));
+
+        // The score dropped as well
+        time.iteration_done(e4, -20);
+        assert_eq!(time.soft_limit().as_millis(), 337);
+
+        // But never past the hard limit
+        time.iteration_done(d4, -100);
+        time.iteration_done(e4, -200);
+        assert_eq!(time.soft_limit(), Duration::from_millis(400));
+        assert!(time.hard_limit_reached(Duration::from_millis(400)));
+    }
+}
@@ -0,0 +1,159 @@
+/*
+    //////////////////////////////
+    /// Transposition table    ///
+    //////////////////////////////
+
+    The same position can be reached through different move orders (1. Nf3 Nf6 2. Nc3 vs 1. Nc3 Nf6 2. Nf3), and
+    iterative deepening visits the same positions again on every iteration. The transposition table remembers what the
+    search found out about a position, keyed by its Zobrist key, so that work isn't done twice.
+
+    For every position we store:
+    - the depth it was searched to, since a shallow result is no good for a deeper search,
+    - the score, along with what kind of score it is (`Bound`). Alpha-beta only gives an exact score when it lands between
+      alpha and beta. A cutoff only tells us "at least this much" and a node where nothing beat alpha only "at most this much",
+    - the best move, which is worth searching first next time even when the score itself can't be used.
+
+    The table has a fixed number of slots (a power of two, so the slot is just the low bits of the key). When two positions
+    want the same slot, the deeper search wins, unless the entry in there is left over from an earlier search.
+*/
+
+use crate::movegen::Move;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower, // The real score is at least this (the search failed high)
+    Upper  // The real score is at most this (the search failed low)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TTEntry {
+    pub key: u64,
+    pub depth: usize,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+    age: u8
+}
+
+pub const DEFAULT_HASH_MB: usize = 16;
+
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+    age: u8
+}
+
+impl TranspositionTable {
+
+    // A table taking up (at most) `megabytes` of memory
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let bytes = megabytes.max(1) * 1024 * 1024;
+        let slots = bytes / std::mem::size_of::<Option<TTEntry>>();
+        // Round down to a power of two, so that the slot of a key is a simple mask
+        let slots = 1 << (usize::BITS - 1 - slots.leading_zeros());
+
+        TranspositionTable {
+            entries: vec![None; slots],
+            age: 0
+        }
+    }
+
+    #[cfg(test)]
+    pub fn slot_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&TTEntry> {
+        self.entries[self.slot(key)].as_ref().filter(|entry| entry.key == key)
+    }
+
+    pub fn store(&mut self, key: u64, depth: usize, score: i32, bound: Bound, best_move: Option<Move>) {
+        let age = self.age;
+        let slot = self.slot(key);
+
+        let replace = match &self.entries[slot] {
+            None => true,
+            Some(old) => old.key == key || old.age != age || depth >= old.depth
+        };
+        if !replace {
+            return;
+        }
+
+        // A search that didn't find a best move shouldn't wipe out the one we already knew
+        let best_move = match (&self.entries[slot], best_move) {
+            (Some(old), None) if old.key == key => old.best_move,
+            _ => best_move
+        };
+
+        self.entries[slot] = Some(TTEntry { key, depth, score, bound, best_move, age });
+    }
+
+    // Called at the start of every search: entries from earlier searches may then be replaced by anything
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.age = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Game;
+
+    #[test]
+    fn size_is_a_power_of_two() {
+        let table = TranspositionTable::new(1);
+        assert!(table.slot_count().is_power_of_two());
+        assert!(table.slot_count() * std::mem::size_of::<Option<TTEntry>>() <= 1024 * 1024);
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let game = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let e2e4 = game.find_legal_move("e2e4");
+        let mut table = TranspositionTable::new(1);
+
+        assert!(table.probe(game.hash).is_none());
+        table.store(game.hash, 5, 30, Bound::Exact, e2e4);
+        let entry = table.probe(game.hash).unwrap();
+        assert_eq!((entry.depth, entry.score, entry.bound, entry.best_move), (5, 30, Bound::Exact, e2e4));
+
+        // Another key landing in the same slot is not a hit
+        let other = game.hash ^ (table.slot_count() as u64);
+        assert!(table.probe(other).is_none());
+
+        table.clear();
+        assert!(table.probe(game.hash).is_none());
+    }
+
+    #[test]
+    fn replace_by_depth() {
+        let mut table = TranspositionTable::new(1);
+        let key = 0x1234_5678;
+        let other = key ^ (table.slot_count() as u64); // same slot, different position
+
+        table.store(key, 6, 10, Bound::Lower, None);
+        // A shallower search of a different position doesn't push out a deeper one...
+        table.store(other, 3, 20, Bound::Exact, None);
+        assert!(table.probe(key).is_some());
+        assert!(table.probe(other).is_none());
+
+        // ...a deeper one does
+        table.store(other, 7, 20, Bound::Exact, None);
+        assert!(table.probe(key).is_none());
+        assert_eq!(table.probe(other).unwrap().depth, 7);
+
+        // Leftovers from an earlier search make way for anything
+        table.new_search();
+        table.store(key, 1, 0, Bound::Upper, None);
+        assert_eq!(table.probe(key).unwrap().depth, 1);
+    }
+}
@@ -0,0 +1,655 @@
+/*
+    ///////////////////////////////////////
+    /// Universal Chess Interface (UCI) ///
+    ///////////////////////////////////////
+
+    UCI is the plain text protocol chess GUIs and match runners use to talk to engines: the GUI writes one command per line
+    on our stdin and we answer on stdout. The handful of commands we care about:
+
+        uci                                   -> identify ourselves, finish with "uciok"
+        isready                               -> "readyok" once we are done with whatever we were doing
+        ucinewgame                            -> forget the old game
+        position startpos [moves e2e4 ...]    -> set up the board
+        position fen <fen> [moves e2e4 ...]
+        go [depth N] [movetime ms] [wtime ms] [btime ms] [winc ms] [binc ms] [movestogo N] [nodes N] [infinite]
+        setoption name Hash value <MB>        -> resize the transposition table
+        setoption name UCI_Chess960 value <true|false> -> castling moves are sent as "king takes own rook"
+        setoption name BookFile value <path>  -> load a Polyglot opening book (see book.rs)
+        setoption name OwnBook value <true|false> -> play moves from that book while there are any
+        setoption name BookBestMove value <true|false> -> always the book's favourite instead of a weighted pick
+        setoption name NullMoveReduction value <N>     -> how much the search prunes and reduces (see search.rs),
+        setoption name LateMoveThreshold value <N>        there to be tuned in self-play. 0 switches that one off.
+        setoption name FutilityMargin value <N>
+        setoption name ReverseFutilityMargin value <N>
+        stop                                  -> stop thinking and answer with "bestmove" right away
+        quit
+
+    Thinking happens on a separate thread, so that we can still read "stop" (or "isready") while the engine is busy.
+    Spec: https://backscattering.de/chess/uci/
+*/
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::book::{Book, BookChoice};
+use crate::magic;
+use crate::search::{self, is_mate_score, mate_in, SearchLimits, SearchParams, SearchResult};
+use crate::timeman::{SystemClock, TimeBudget};
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB};
+use crate::{Color, Game, STARTING_FEN};
+
+// Everything that can follow "go". Times are in milliseconds, anything the GUI did not send stays None.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct GoOptions {
+    pub depth: Option<usize>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub nodes: Option<u64>,
+    pub infinite: bool
+}
+
+fn parse_go(tokens: &[&str]) -> GoOptions {
+    let mut options = GoOptions::default();
+    let mut tokens = tokens.iter();
+
+    while let Some(&token) = tokens.next() {
+        // Every option except "infinite" is followed by a number. Values we can't parse are ignored, just like unknown options.
+        let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+        match token {
+            "depth" => options.depth = value().map(|depth| depth as usize),
+            "movetime" => options.movetime = value(),
+            "wtime" => options.wtime = value(),
+            "btime" => options.btime = value(),
+            "winc" => options.winc = value(),
+            "binc" => options.binc = value(),
+            "movestogo" => options.movestogo = value(),
+            "nodes" => options.nodes = value(),
+            "infinite" => options.infinite = true,
+            _ => ()
+        }
+    }
+
+    options
+}
+
+// Turns the "go" options into limits for the search. How much of the clock to use is worked out in timeman.rs.
+fn search_limits(options: &GoOptions, color: Color, stop: Arc<AtomicBool>, start: Instant) -> SearchLimits {
+    let mut limits = SearchLimits {
+        depth: options.depth,
+        nodes: options.nodes,
+        stop: Some(stop),
+        // Our time started running when the GUI sent "go", not when the search gets going
+        clock: Some(Arc::new(SystemClock::new(start))),
+        ..SearchLimits::default()
+    };
+
+    if options.infinite {
+        return limits;
+    }
+
+    let (time, increment) = match color {
+        Color::White => (options.wtime, options.winc),
+        Color::Black => (options.btime, options.binc)
+    };
+
+    limits.budget = match (options.movetime, time) {
+        (Some(movetime), _) => Some(TimeBudget::fixed(Duration::from_millis(movetime))),
+        (None, Some(time)) => Some(TimeBudget::from_clock(time, increment.unwrap_or(0), options.movestogo)),
+        (None, None) => None
+    };
+
+    limits
+}
+
+// "info depth 5 score cp 31 nodes 12345 nps 400000 time 30 pv e2e4 e7e5 ..."
+fn info_line(result: &SearchResult, elapsed: Duration) -> String {
+    let score = if is_mate_score(result.score) {
+        format!("mate {}", mate_in(result.score))
+    } else {
+        format!("cp {}", result.score)
+    };
+    let millis = elapsed.as_millis() as u64;
+    let nps = result.nodes * 1000 / millis.max(1);
+    let pv: Vec<String> = result.pv.iter().map(|m| m.to_string()).collect();
+
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        result.depth, score, result.nodes, nps, millis, pv.join(" ")
+    )
+}
+
+fn send<W: Write>(out: &Arc<Mutex<W>>, line: &str) {
+    // If the GUI went away there is nobody left to complain to, so write errors are ignored.
+    if let Ok(mut out) = out.lock() {
+        let _ = writeln!(out, "{}", line);
+        let _ = out.flush();
+    }
+}
+
+// A search that panicked leaves the table's mutex poisoned, and maybe an entry half written. Rather than taking every
+// later search down with it, we start over with an empty table.
+fn lock_table(tt: &Mutex<TranspositionTable>) -> MutexGuard<'_, TranspositionTable> {
+    match tt.lock() {
+        Ok(tt) => tt,
+        Err(poisoned) => {
+            let mut table = poisoned.into_inner();
+            table.clear();
+            tt.clear_poison();
+            table
+        }
+    }
+}
+
+pub struct Uci<W: Write + Send + 'static> {
+    game: Game,
+    out: Arc<Mutex<W>>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+    // Shared with the search thread, and kept between moves of the same game
+    tt: Arc<Mutex<TranspositionTable>>,
+    chess960: bool, // the GUI plays Chess960, see `Game.chess960`
+    book: Option<Book>,
+    own_book: bool,
+    book_choice: BookChoice,
+    params: SearchParams
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+
+    pub fn new(out: Arc<Mutex<W>>) -> Uci<W> {
+        Uci {
+            game: Game::read_FEN(STARTING_FEN).expect("the starting position is a valid FEN"),
+            out,
+            stop: Arc::new(AtomicBool::new(false)),
+            search: None,
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_MB))),
+            chess960: false,
+            book: None,
+            own_book: false,
+            book_choice: BookChoice::Weighted,
+            params: SearchParams::default()
+        }
+    }
+
+    // Handles one line from the GUI. Returns false once the GUI has told us to quit.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                send(&self.out, "id name chessengine");
+                send(&self.out, "id author saxenism");
+                send(&self.out, &format!("option name Hash type spin default {} min 1 max 1024", DEFAULT_HASH_MB));
+                send(&self.out, "option name UCI_Chess960 type check default false");
+                send(&self.out, "option name OwnBook type check default false");
+                send(&self.out, "option name BookFile type string default <empty>");
+                send(&self.out, "option name BookBestMove type check default false");
+                let mut defaults = SearchParams::default();
+                for (name, max) in SearchParams::OPTIONS {
+                    let default = defaults.by_name(name).map_or(0, |value| *value);
+                    send(&self.out, &format!("option name {} type spin default {} min 0 max {}", name, default, max));
+                }
+                send(&self.out, "uciok");
+            },
+            Some(&"isready") => send(&self.out, "readyok"),
+            Some(&"ucinewgame") => {
+                self.stop_search();
+                self.game = Game::read_FEN(STARTING_FEN).expect("the starting position is a valid FEN");
+                lock_table(&self.tt).clear();
+            },
+            Some(&"setoption") => {
+                self.stop_search();
+                self.set_option(&tokens[1..]);
+            },
+            Some(&"position") => {
+                self.stop_search();
+                self.set_position(&tokens[1..]);
+            },
+            Some(&"go") => self.go(parse_go(&tokens[1..])),
+            Some(&"stop") => self.stop_search(),
+            Some(&"quit") => {
+                self.stop_search();
+                return false;
+            },
+            // The protocol says to silently ignore anything we don't understand
+            _ => ()
+        }
+
+        true
+    }
+
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_start = tokens.iter().position(|&token| token == "moves").unwrap_or(tokens.len());
+
+        let game = match tokens.first() {
+            Some(&"startpos") => Game::read_FEN(STARTING_FEN),
+            Some(&"fen") => Game::read_FEN(&tokens[1..moves_start].join(" ")),
+            _ => {
+                send(&self.out, "info string position needs either startpos or fen");
+                return;
+            }
+        };
+
+        let mut game = match game {
+            Ok(game) => game,
+            Err(error) => {
+                send(&self.out, &format!("info string {}", error));
+                return;
+            }
+        };
+        // A Chess960 start can look just like the normal one (KQkq), so the GUI's word counts as well
+        game.chess960 |= self.chess960;
+
+        for &coordinates in tokens.iter().skip(moves_start + 1) {
+            match game.find_legal_move(coordinates) {
+                Some(m) => game.make_move(m),
+                None => {
+                    send(&self.out, &format!("info string illegal move {} in position {}", coordinates, game.to_FEN()));
+                    break;
+                }
+            }
+        }
+
+        self.game = game;
+    }
+
+    // "setoption name <name> [value <value>]".
+    fn set_option(&mut self, tokens: &[&str]) {
+        let value_start = tokens.iter().position(|&token| token == "value").unwrap_or(tokens.len());
+        let name = tokens.get(1..value_start).unwrap_or(&[]).join(" ");
+        let value = tokens.get(value_start + 1..).unwrap_or(&[]).join(" ");
+
+        if name.eq_ignore_ascii_case("hash") {
+            match value.parse::<usize>() {
+                Ok(megabytes) if (1..=1024).contains(&megabytes) => {
+                    self.tt = Arc::new(Mutex::new(TranspositionTable::new(megabytes)));
+                },
+                _ => send(&self.out, &format!("info string invalid Hash value {}", value))
+            }
+        } else if name.eq_ignore_ascii_case("uci_chess960") {
+            match value.to_ascii_lowercase().as_str() {
+                "true" => self.chess960 = true,
+                "false" => self.chess960 = false,
+                _ => send(&self.out, &format!("info string invalid UCI_Chess960 value {}", value))
+            }
+        } else if name.eq_ignore_ascii_case("ownbook") {
+            match value.to_ascii_lowercase().as_str() {
+                "true" => self.own_book = true,
+                "false" => self.own_book = false,
+                _ => send(&self.out, &format!("info string invalid OwnBook value {}", value))
+            }
+        } else if name.eq_ignore_ascii_case("bookbestmove") {
+            match value.to_ascii_lowercase().as_str() {
+                "true" => self.book_choice = BookChoice::Best,
+                "false" => self.book_choice = BookChoice::Weighted,
+                _ => send(&self.out, &format!("info string invalid BookBestMove value {}", value))
+            }
+        } else if name.eq_ignore_ascii_case("bookfile") {
+            self.book = None;
+            if value.is_empty() || value == "<empty>" {
+                return;
+            }
+            match Book::open(Path::new(&value)) {
+                Ok(book) => self.book = Some(book),
+                Err(error) => send(&self.out, &format!("info string could not read book {}: {}", value, error))
+            }
+        } else if let Some(&(option, max)) = SearchParams::OPTIONS.iter().find(|(option, _)| option.eq_ignore_ascii_case(&name)) {
+            match value.parse::<i32>() {
+                Ok(number) if (0..=max).contains(&number) => {
+                    *self.params.by_name(option).expect("every option has a parameter") = number;
+                },
+                _ => send(&self.out, &format!("info string invalid {} value {}", name, value))
+            }
+        } else {
+            send(&self.out, &format!("info string unknown option {}", name));
+        }
+    }
+
+    // A move from the opening book, if it is switched on and still knows the position
+    fn book_move(&self) -> Option<crate::movegen::Move> {
+        let book = self.book.as_ref().filter(|_| self.own_book)?;
+        // Which of the book moves we pick doesn't need to be any more random than the clock
+        let random = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos() as u64).unwrap_or(0);
+        book.pick(&self.game, self.book_choice, random)
+    }
+
+    fn go(&mut self, options: GoOptions) {
+        self.stop_search();
+        self.stop.store(false, Ordering::SeqCst);
+
+        // "go infinite" is meant for analysis, the book has nothing to say there
+        if !options.infinite {
+            if let Some(m) = self.book_move() {
+                send(&self.out, &format!("bestmove {}", m));
+                return;
+            }
+        }
+
+        let start = Instant::now();
+        let mut game = self.game.clone();
+        let out = Arc::clone(&self.out);
+        let stop = Arc::clone(&self.stop);
+        let tt = Arc::clone(&self.tt);
+        let mut limits = search_limits(&options, game.active_color, Arc::clone(&stop), start);
+        limits.params = self.params;
+
+        self.search = Some(thread::spawn(move || {
+            let mut tt = lock_table(&tt);
+            let result = search::search_with_progress(&mut game, &limits, &mut tt, &mut |info| {
+                send(&out, &info_line(info, start.elapsed()));
+            });
+            drop(tt);
+            let best = result.best_move;
+
+            // With "go infinite" the GUI expects us to keep quiet until it sends "stop"
+            if options.infinite {
+                while !stop.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+
+            match best {
+                Some(m) => send(&out, &format!("bestmove {}", m)),
+                // No legal moves (mate or stalemate): UCI's way of saying "null move"
+                None => send(&out, "bestmove 0000")
+            }
+        }));
+    }
+
+    // Tells a running search to wrap up and waits until it has sent its "bestmove".
+    pub fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
+}
+
+pub fn run() {
+    // Get the attack tables ready before the GUI starts the clock on us
+    magic::init();
+    let mut uci = Uci::new(Arc::new(Mutex::new(io::stdout())));
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break
+        };
+        if !uci.handle(&line) {
+            return;
+        }
+    }
+
+    // stdin was closed without a "quit", still let a running search finish its answer
+    uci.stop_search();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> (Uci<Vec<u8>>, Arc<Mutex<Vec<u8>>>) {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        (Uci::new(Arc::clone(&out)), out)
+    }
+
+    // Lets the running search finish on its own (stop_search would cut it short)
+    fn wait_for_search(uci: &mut Uci<Vec<u8>>) {
+        if let Some(search) = uci.search.take() {
+            search.join().unwrap();
+        }
+    }
+
+    // Everything written so far, emptying the buffer
+    fn output(out: &Arc<Mutex<Vec<u8>>>) -> Vec<String> {
+        let mut buffer = out.lock().unwrap();
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        buffer.clear();
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn handshake() {
+        let (mut uci, out) = engine();
+        assert!(uci.handle("uci"));
+        assert_eq!(output(&out).last().unwrap(), "uciok");
+
+        uci.handle("isready");
+        assert_eq!(output(&out), vec!["readyok"]);
+
+        uci.handle("some command from the future");
+        assert!(output(&out).is_empty());
+        assert!(!uci.handle("quit"));
+    }
+
+    #[test]
+    fn position_with_moves() {
+        let (mut uci, out) = engine();
+        uci.handle("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(uci.game.to_FEN(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        uci.handle("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1");
+        assert_eq!(uci.game.to_FEN(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        assert!(output(&out).is_empty());
+
+        uci.handle("position startpos moves e2e5");
+        assert!(output(&out)[0].starts_with("info string illegal move e2e5"));
+
+        uci.handle("position fen 4k3/8/8 w - - 0 1");
+        assert!(output(&out)[0].starts_with("info string Invalid FEN"));
+    }
+
+    #[test]
+    fn go_options() {
+        let options = parse_go(&["wtime", "60000", "btime", "55000", "winc", "1000", "binc", "1000", "movestogo", "20"]);
+        assert_eq!(options.wtime, Some(60000));
+        assert_eq!(options.btime, Some(55000));
+        assert_eq!(options.winc, Some(1000));
+        assert_eq!(options.binc, Some(1000));
+        assert_eq!(options.movestogo, Some(20));
+        assert!(!options.infinite);
+
+        let options = parse_go(&["depth", "6", "infinite", "movetime", "oops"]);
+        assert_eq!(options.depth, Some(6));
+        assert!(options.infinite);
+        assert_eq!(options.movetime, None);
+    }
+
+    #[test]
+    fn go_answers_with_a_legal_bestmove() {
+        let (mut uci, out) = engine();
+        uci.handle("position startpos moves e2e4");
+        uci.handle("go depth 1");
+        wait_for_search(&mut uci);
+
+        let lines = output(&out);
+        let bestmove = lines.last().unwrap().strip_prefix("bestmove ").unwrap();
+        assert!(uci.game.find_legal_move(bestmove).is_some());
+
+        // Checkmated: nothing to play
+        uci.handle("position fen rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        uci.handle("go depth 1");
+        wait_for_search(&mut uci);
+        assert_eq!(output(&out).last().unwrap(), "bestmove 0000");
+    }
+
+    #[test]
+    fn go_reports_info_lines() {
+        let (mut uci, out) = engine();
+        uci.handle("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        uci.handle("go depth 3");
+        wait_for_search(&mut uci);
+
+        let lines = output(&out);
+        assert!(lines[0].starts_with("info depth 1 score mate 1 nodes "));
+        assert!(lines[0].ends_with("pv a1a8"));
+        assert_eq!(lines.last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn clock_budget() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+        let millis = |limits: SearchLimits| limits.budget.map(|budget| (budget.soft.as_millis(), budget.hard.as_millis()));
+
+        let options = parse_go(&["wtime", "30050", "btime", "1050", "winc", "2000"]);
+        assert_eq!(millis(search_limits(&options, Color::White, Arc::clone(&stop), start)), Some((2500, 10_000)));
+        // Black has no increment and hardly any time
+        assert_eq!(millis(search_limits(&options, Color::Black, Arc::clone(&stop), start)), Some((33, 132)));
+
+        let options = parse_go(&["movetime", "250", "depth", "4"]);
+        let limits = search_limits(&options, Color::Black, Arc::clone(&stop), start);
+        assert_eq!(limits.depth, Some(4));
+        assert_eq!(millis(limits), Some((250, 250)));
+
+        let options = parse_go(&["infinite", "wtime", "30000"]);
+        assert_eq!(search_limits(&options, Color::White, stop, start).budget, None);
+    }
+
+    #[test]
+    fn answers_with_almost_no_time_left() {
+        // Less than the move overhead on the clock: no time to think, but a legal move all the same
+        let (mut uci, out) = engine();
+        uci.handle("position startpos moves e2e4");
+        uci.handle("go wtime 20 btime 20");
+        wait_for_search(&mut uci);
+        let bestmove = output(&out).pop().unwrap();
+        let m = bestmove.strip_prefix("bestmove ").unwrap();
+        assert!(uci.game.find_legal_move(m).is_some(), "{}", bestmove);
+    }
+
+    #[test]
+    fn hash_option() {
+        let (mut uci, out) = engine();
+        uci.handle("uci");
+        assert!(output(&out).contains(&"option name Hash type spin default 16 min 1 max 1024".to_string()));
+
+        uci.handle("setoption name Hash value 2");
+        assert!(output(&out).is_empty());
+        assert_eq!(uci.tt.lock().unwrap().slot_count(), TranspositionTable::new(2).slot_count());
+
+        uci.handle("setoption name Hash value lots");
+        assert_eq!(output(&out), vec!["info string invalid Hash value lots"]);
+        uci.handle("setoption name Ponder value true");
+        assert_eq!(output(&out), vec!["info string unknown option Ponder"]);
+    }
+
+    #[test]
+    fn search_options() {
+        let (mut uci, out) = engine();
+        uci.handle("uci");
+        assert!(output(&out).contains(&"option name NullMoveReduction type spin default 2 min 0 max 4".to_string()));
+
+        uci.handle("setoption name LateMoveThreshold value 0");
+        uci.handle("setoption name futilitymargin value 200");
+        assert!(output(&out).is_empty());
+        assert_eq!(uci.params.late_move_threshold, 0);
+        assert_eq!(uci.params.futility_margin, 200);
+
+        uci.handle("setoption name NullMoveReduction value 9");
+        assert_eq!(output(&out), vec!["info string invalid NullMoveReduction value 9"]);
+        assert_eq!(uci.params.null_move_reduction, SearchParams::default().null_move_reduction);
+
+        // The search still finds its move with everything switched off
+        for (name, _) in SearchParams::OPTIONS {
+            uci.handle(&format!("setoption name {} value 0", name));
+        }
+        assert_eq!(uci.params, SearchParams::OFF);
+        uci.handle("go depth 3");
+        wait_for_search(&mut uci);
+        assert!(output(&out).last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn survives_a_panicked_search() {
+        let (mut uci, out) = engine();
+        let tt = Arc::clone(&uci.tt);
+        let _ = thread::spawn(move || {
+            let _tt = tt.lock().unwrap();
+            panic!("a search went wrong");
+        })
+        .join();
+        assert!(uci.tt.is_poisoned());
+
+        uci.handle("position startpos");
+        uci.handle("go depth 2");
+        wait_for_search(&mut uci);
+        assert!(output(&out).last().unwrap().starts_with("bestmove "));
+        assert!(!uci.tt.is_poisoned());
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let (mut uci, out) = engine();
+        uci.handle("setoption name UCI_Chess960 value true");
+        assert!(output(&out).is_empty());
+
+        // Start position 518 is the normal setup, but castling is now written as the king taking its own rook
+        uci.handle("position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1h1");
+        assert_eq!(uci.game.to_FEN(), "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4");
+
+        uci.handle("position fen 1r1k2r1/8/8/8/8/8/8/1R1K2R1 w GBgb - 0 1 moves d1b1");
+        assert!(output(&out).is_empty());
+        assert_eq!(uci.game.to_FEN(), "1r1k2r1/8/8/8/8/8/8/2KR2R1 b kq - 1 1");
+    }
+
+    #[test]
+    fn opening_book() {
+        let path = std::env::temp_dir().join(format!("chessengine-uci-book-{}.bin", std::process::id()));
+        std::fs::write(&path, crate::book::fixture()).unwrap();
+
+        let (mut uci, out) = engine();
+        uci.handle(&format!("setoption name BookFile value {}", path.display()));
+        uci.handle("setoption name OwnBook value true");
+        std::fs::remove_file(&path).unwrap();
+        assert!(output(&out).is_empty());
+
+        // Straight from the book, without searching
+        uci.handle("position startpos");
+        uci.handle("go depth 3");
+        let lines = output(&out);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0] == "bestmove e2e4" || lines[0] == "bestmove d2d4", "{:?}", lines);
+        uci.handle("setoption name BookBestMove value true");
+        uci.handle("go depth 3");
+        assert_eq!(output(&out), vec!["bestmove e2e4"]);
+
+        // The book plays castling the way the GUI expects it
+        uci.handle("position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 e1g1 g8f6 d2d3");
+        uci.handle("go depth 3");
+        assert_eq!(output(&out), vec!["bestmove e8g8"]);
+
+        // Out of book, or with the book switched off, the engine thinks for itself
+        uci.handle("position startpos moves a2a3");
+        uci.handle("go depth 1");
+        wait_for_search(&mut uci);
+        assert!(output(&out)[0].starts_with("info depth 1"));
+        uci.handle("setoption name OwnBook value false");
+        uci.handle("position startpos");
+        uci.handle("go depth 1");
+        wait_for_search(&mut uci);
+        assert!(output(&out)[0].starts_with("info depth 1"));
+
+        uci.handle("setoption name BookFile value /no/such/book.bin");
+        assert!(output(&out)[0].starts_with("info string could not read book /no/such/book.bin"));
+        assert!(uci.book.is_none());
+    }
+
+    #[test]
+    fn go_infinite_waits_for_stop() {
+        let (mut uci, out) = engine();
+        uci.handle("go infinite");
+        thread::sleep(Duration::from_millis(20));
+        assert!(!output(&out).iter().any(|line| line.starts_with("bestmove")));
+
+        uci.handle("stop");
+        assert!(output(&out).last().unwrap().starts_with("bestmove "));
+    }
+}
@@ -0,0 +1,209 @@
+/*
+    ///////////////////////
+    /// Zobrist hashing ///
+    ///////////////////////
+
+    A Zobrist key squeezes a whole position into a single u64, so that positions can be compared (repetitions) and looked
+    up (the transposition table) without comparing boards square by square.
+
+    Every "feature" of a position gets a fixed random number: one per (color, piece type, square), one for "black to move",
+    one per combination of castling rights and one per en passant file. The key of a position is all the numbers of its
+    features XOR-ed together. Since x ^ k ^ k == x, a move only has to XOR out what disappeared and XOR in what appeared
+    (a knight leaving g1 and arriving on f3 is two XORs), instead of recomputing the key from scratch.
+
+    The en passant file only counts when the side to move actually has a pawn that can capture en passant. Otherwise the
+    position after 1. e4 would never be a repetition of the "same" position reached with the pawn walking e3-e4.
+*/
+
+use crate::movegen::pawn_attacks;
+use crate::{CastlingRights, Color, Game, PieceType};
+
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    black_to_move: u64,
+    castling: [u64; 16],
+    en_passant_file: [u64; 8]
+}
+
+// SplitMix64, a tiny pseudo random number generator. It is good enough for hashing and, being a const fn,
+// lets the compiler produce all the keys at compile time. Returns (next state, random number).
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys() -> ZobristKeys {
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        black_to_move: 0,
+        castling: [0; 16],
+        en_passant_file: [0; 8]
+    };
+
+    // Any seed will do, it just has to be the same every time
+    let mut state = 0x00c0_ffee_5eed_1234;
+    let mut color = 0;
+    while color < 2 {
+        let mut piece_type = 0;
+        while piece_type < 6 {
+            let mut square = 0;
+            while square < 64 {
+                let (next, key) = splitmix64(state);
+                state = next;
+                keys.pieces[color][piece_type][square] = key;
+                square += 1;
+            }
+            piece_type += 1;
+        }
+        color += 1;
+    }
+
+    let (next, key) = splitmix64(state);
+    state = next;
+    keys.black_to_move = key;
+
+    let mut rights = 0;
+    while rights < 16 {
+        let (next, key) = splitmix64(state);
+        state = next;
+        // No castling rights at all hashes to 0, so a position without rights doesn't need a castling key
+        keys.castling[rights] = if rights == 0 { 0 } else { key };
+        rights += 1;
+    }
+
+    let mut file = 0;
+    while file < 8 {
+        let (next, key) = splitmix64(state);
+        state = next;
+        keys.en_passant_file[file] = key;
+        file += 1;
+    }
+
+    keys
+}
+
+static KEYS: ZobristKeys = generate_keys();
+
+pub fn piece_key(color: Color, piece_type: PieceType, square: usize) -> u64 {
+    KEYS.pieces[color as usize][piece_type as usize][square]
+}
+
+pub fn side_key() -> u64 {
+    KEYS.black_to_move
+}
+
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    KEYS.castling[rights.bits() as usize]
+}
+
+impl Game {
+
+    // The en passant part of the key: the target square's file, but only if the side to move can really capture there.
+    pub fn en_passant_key(&self) -> u64 {
+        let target = match self.en_passant {
+            Some(target) => target,
+            None => return 0
+        };
+
+        let target_square = crate::find_set_bit(target);
+        let us = self.active_color;
+        // Squares from which a pawn of ours would attack the target square
+        let attackers = pawn_attacks(target_square, us.opposite());
+        if attackers & self.boards[us as usize][PieceType::Pawn as usize] != 0 {
+            KEYS.en_passant_file[target_square % 8]
+        } else {
+            0
+        }
+    }
+
+    // The key of the current position, worked out from scratch. `make_move` keeps `Game.hash` up to date incrementally,
+    // this is used when a position is set up and to double check the incremental updates.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (square, piece) in self.mailbox.iter().enumerate() {
+            if let Some(piece) = piece {
+                hash ^= piece_key(piece.color, piece.piece_type, square);
+            }
+        }
+
+        if self.active_color == Color::Black {
+            hash ^= side_key();
+        }
+
+        hash ^ castling_key(self.castling_rights) ^ self.en_passant_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &str) {
+        for coordinates in moves.split_whitespace() {
+            let m = game.find_legal_move(coordinates).unwrap();
+            game.make_move(m);
+        }
+    }
+
+    // Walks the whole move tree and checks that the incrementally updated key always matches a fresh computation
+    fn check_incremental(game: &mut Game, depth: usize) {
+        assert_eq!(game.hash, game.compute_hash(), "{}", game.to_FEN());
+        if depth == 0 {
+            return;
+        }
+        for m in game.legal_moves() {
+            let before = game.hash;
+            game.make_move(m);
+            check_incremental(game, depth - 1);
+            game.unmake_move();
+            assert_eq!(game.hash, before);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_computation() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"
+        ] {
+            let mut game = Game::read_FEN(fen).unwrap();
+            check_incremental(&mut game, 3);
+        }
+    }
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut a = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        let mut b = Game::read_FEN(crate::STARTING_FEN).unwrap();
+        play(&mut a, "g1f3 g8f6 b1c3 b8c6");
+        play(&mut b, "b1c3 b8c6 g1f3 g8f6");
+        assert_eq!(a.hash, b.hash);
+
+        // Same pieces, different side to move
+        let white = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let black = Game::read_FEN("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(white.hash, black.hash);
+
+        // Same pieces, different castling rights
+        let castling = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let no_castling = Game::read_FEN("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        assert_ne!(castling.hash, no_castling.hash);
+    }
+
+    #[test]
+    fn en_passant_only_counts_when_capturable() {
+        // Nobody can take on e3, so the target square doesn't change the key
+        let with_target = Game::read_FEN("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+        let without = Game::read_FEN("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(with_target.hash, without.hash);
+
+        // The pawn on d4 can take on e3, so now it does
+        let with_target = Game::read_FEN("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        let without = Game::read_FEN("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(with_target.hash, without.hash);
+    }
+}